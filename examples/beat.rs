@@ -2,7 +2,7 @@ extern crate libpulse_binding as pulse;
 extern crate libpulse_simple_binding as psimple;
 extern crate monotron_synth;
 
-use monotron_synth::{Channel, Note, Synth, Waveform, MAX_VOLUME};
+use monotron_synth::{Channel, Note, Synth, Waveform, CHANNEL_0, CHANNEL_1, CHANNEL_2, MAX_VOLUME};
 use psimple::Simple;
 use pulse::stream::Direction;
 use std::io::Write;
@@ -11,6 +11,7 @@ const SAMPLE_RATE: u32 = 80_000_000 / 2112;
 const FRAME_LENGTH_SAMPLES: usize = SAMPLE_RATE as usize / 60;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 enum Error {
     AudioError(pulse::error::PAErr),
     IOError(std::io::Error),
@@ -52,6 +53,7 @@ fn main() -> Result<(), Error> {
 
     let mut output_file = std::fs::File::create("audio.raw")?;
 
+    #[allow(clippy::type_complexity)]
     struct Track<'a> {
         channel: Channel,
         play_idx: usize,
@@ -61,7 +63,7 @@ fn main() -> Result<(), Error> {
 
     // Bass line
     let mut track0 = Track {
-        channel: Channel::Channel0,
+        channel: CHANNEL_0,
         play_idx: 0,
         max_frames: 120,
         notes: &[
@@ -86,7 +88,7 @@ fn main() -> Result<(), Error> {
 
     // Hi-hat
     let mut track1 = Track {
-        channel: Channel::Channel1,
+        channel: CHANNEL_1,
         play_idx: 0,
         max_frames: 120,
         notes: &[
@@ -101,7 +103,7 @@ fn main() -> Result<(), Error> {
 
     // Scale
     let mut track2 = Track {
-        channel: Channel::Channel2,
+        channel: CHANNEL_2,
         play_idx: 0,
         max_frames: 120,
         notes: &[
@@ -145,12 +147,9 @@ fn main() -> Result<(), Error> {
         println!("{}", frame_count);
         // Play a frame
         let mut samples = [0; FRAME_LENGTH_SAMPLES];
-        for sample in samples.iter_mut() {
-            let s = synth.next();
-            *sample = s.into();
-            if *sample == 0 || *sample == 255 {
-                print!("Clip!");
-            }
+        synth.render_u8(&mut samples);
+        if samples.iter().any(|&sample| sample == 0 || sample == 255) {
+            print!("Clip!");
         }
         output_file.write_all(&samples)?;
         s.write(&samples)?;
@@ -2,7 +2,7 @@ extern crate libpulse_binding as pulse;
 extern crate libpulse_simple_binding as psimple;
 extern crate monotron_synth;
 
-use monotron_synth::{Channel, Note, Synth, Waveform, MAX_VOLUME};
+use monotron_synth::{Note, Synth, Waveform, CHANNEL_0, CHANNEL_1, CHANNEL_2, MAX_VOLUME};
 use psimple::Simple;
 use pulse::stream::Direction;
 
@@ -12,7 +12,7 @@ const FRAME_LENGTH_SAMPLES: usize = SAMPLE_RATE as usize / 60;
 fn main() -> Result<(), pulse::error::PAErr> {
     let spec = pulse::sample::Spec {
         format: pulse::sample::Format::U8,
-        channels: 1,
+        channels: 2,
         rate: SAMPLE_RATE,
     };
     assert!(spec.is_valid());
@@ -30,13 +30,16 @@ fn main() -> Result<(), pulse::error::PAErr> {
     .unwrap();
 
     let mut synth = Synth::new(SAMPLE_RATE);
+    // Spread the chord out across the stereo image
+    synth.set_pan(CHANNEL_0, 0);
+    synth.set_pan(CHANNEL_2, 255);
 
     let notes = [
-        (Channel::Channel0, Note::C4, 0, MAX_VOLUME),
-        (Channel::Channel1, Note::E4, 30, MAX_VOLUME),
-        (Channel::Channel2, Note::G4, 60, MAX_VOLUME),
-        (Channel::Channel2, Note::G4, 90, 0),
-        (Channel::Channel1, Note::E4, 120, 0),
+        (CHANNEL_0, Note::C4, 0, MAX_VOLUME),
+        (CHANNEL_1, Note::E4, 30, MAX_VOLUME),
+        (CHANNEL_2, Note::G4, 60, MAX_VOLUME),
+        (CHANNEL_2, Note::G4, 90, 0),
+        (CHANNEL_1, Note::E4, 120, 0),
     ];
 
     const WAVEFORM: Waveform = Waveform::Sawtooth;
@@ -56,13 +59,10 @@ fn main() -> Result<(), pulse::error::PAErr> {
             println!("{}", frame_count);
             frame_count += 1;
             // Play a frame
-            let mut samples = [0; FRAME_LENGTH_SAMPLES];
-            for sample in samples.iter_mut() {
-                let s = synth.next();
-                *sample = s.into();
-                if *sample == 0 || *sample == 255 {
-                    print!("Clip!");
-                }
+            let mut samples = [0; FRAME_LENGTH_SAMPLES * 2];
+            synth.render_stereo_u8(&mut samples);
+            if samples.iter().any(|&sample| sample == 0 || sample == 255) {
+                print!("Clip!");
             }
             s.write(&samples)?;
         }
@@ -2,7 +2,7 @@ extern crate libpulse_binding as pulse;
 extern crate libpulse_simple_binding as psimple;
 extern crate monotron_synth;
 
-use monotron_synth::{Channel, Note, Synth, Waveform, MAX_VOLUME};
+use monotron_synth::{Channel, Note, Synth, Waveform, CHANNEL_0, CHANNEL_1, CHANNEL_2, MAX_VOLUME};
 use psimple::Simple;
 use pulse::stream::Direction;
 use std::io::Write;
@@ -12,6 +12,7 @@ const FRAME_LENGTH_SAMPLES: usize = SAMPLE_RATE as usize / 60;
 const FRAMES_PER_BAR: usize = 128;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 enum Error {
     AudioError(pulse::error::PAErr),
     IOError(std::io::Error),
@@ -54,6 +55,7 @@ fn main() -> Result<(), Error> {
     let mut output_file = std::fs::File::create("audio.raw")?;
 
     #[derive(Debug)]
+    #[allow(dead_code)]
     enum Length {
         Whole,
         Half,
@@ -83,7 +85,7 @@ fn main() -> Result<(), Error> {
     // --- E3
 
     let mut track0 = Track {
-        channel: Channel::Channel0,
+        channel: CHANNEL_0,
         play_idx: 0,
         play_next_at: 0,
         waveform: Waveform::Square,
@@ -141,7 +143,7 @@ fn main() -> Result<(), Error> {
     };
 
     let mut track1 = Track {
-        channel: Channel::Channel1,
+        channel: CHANNEL_1,
         play_idx: 0,
         waveform: Waveform::Square,
         volume: MAX_VOLUME / 2,
@@ -208,7 +210,7 @@ fn main() -> Result<(), Error> {
     };
 
     let mut track2 = Track {
-        channel: Channel::Channel2,
+        channel: CHANNEL_2,
         play_idx: 0,
         waveform: Waveform::Sawtooth,
         volume: MAX_VOLUME,
@@ -324,12 +326,9 @@ fn main() -> Result<(), Error> {
         println!("{}", frame_count);
         // Play a frame
         let mut samples = [0; FRAME_LENGTH_SAMPLES];
-        for sample in samples.iter_mut() {
-            let s = synth.next();
-            *sample = s.into();
-            if *sample == 0 || *sample == 255 {
-                print!("Clip!");
-            }
+        synth.render_u8(&mut samples);
+        if samples.iter().any(|&sample| sample == 0 || sample == 255) {
+            print!("Clip!");
         }
         output_file.write_all(&samples)?;
         s.write(&samples)?;
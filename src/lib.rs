@@ -2,58 +2,394 @@
 
 pub const MAX_VOLUME: u8 = 255;
 
-/// We have a three channel synthesiser.
-#[derive(Debug, Copy, Clone)]
-pub enum Channel {
-    Channel0,
-    Channel1,
-    Channel2,
-}
+/// Selects one of the channels on a `Synth` with `N` channels. The index is
+/// checked when the `Channel` is created, so it is always valid for that
+/// `Synth`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Channel<const N: usize = 3>(usize);
 
-pub const CHANNEL_0: Channel = Channel::Channel0;
-pub const CHANNEL_1: Channel = Channel::Channel1;
-pub const CHANNEL_2: Channel = Channel::Channel2;
+pub const CHANNEL_0: Channel = Channel(0);
+pub const CHANNEL_1: Channel = Channel(1);
+pub const CHANNEL_2: Channel = Channel(2);
 
 /// Our synthesiser. You can tell it to play notes, then repeatedly ask it for
 /// samples (which are calculated as you ask for them). You could either
 /// buffer those samples and dispatch the to a PC sound card, or pass them to
 /// a DAC in real-time. The samples are calculated by summing together the
-/// outputs of the four channels.
-pub struct Synth {
+/// outputs of the `N` channels. By default we have three channels.
+pub struct Synth<const N: usize = 3> {
     sample_rate: u32,
-    channels: [Oscillator; 3],
+    channels: [Oscillator; N],
+    clip_mode: ClipMode,
+    /// The gain applied to the whole mix, with 16 fractional bits, where
+    /// `256 << 16` is unity.
+    master_level: i32,
+    /// How much `master_level` changes each sample during a fade.
+    fade_step: i32,
+    /// Where `master_level` will be when the fade finishes.
+    fade_target: i32,
+    /// How many samples are left in the current fade.
+    fade_remaining: u32,
+    /// Are any channels routed through the filter?
+    filter_in_use: bool,
+    /// The filter for the mono mix, or the left side of the stereo mix.
+    filter_left: StateVariableFilter,
+    /// The filter for the right side of the stereo mix.
+    filter_right: StateVariableFilter,
+    /// How we turn a `Note` into a `Frequency`.
+    tuning: Tuning,
 }
 
-/// Our oscillator produces one of four waveforms.
+/// Our oscillator produces one of these waveforms.
 #[derive(Debug, Copy, Clone)]
 pub enum Waveform {
     Sine,
     Sawtooth,
     Square,
+    Triangle,
+    /// A stepped triangle wave, like the one produced by the NES.
+    NesTriangle,
+    /// White noise, from a 15-bit linear-feedback shift register clocked at
+    /// 256 times the note frequency.
     Noise,
+    /// Like `Noise`, but using the short 93-step sequence, which gives a
+    /// metallic, more tonal sound.
+    PeriodicNoise,
+    /// A square wave with a variable duty cycle. See `Synth::set_duty_cycle`.
+    Pulse,
+    /// A single cycle of your own waveform, sampled as 256 signed 8-bit
+    /// values. See `wavetable_from_harmonics` for one way to make these.
+    Custom(&'static [i8; 256]),
 }
 
-/// Our `Synth` has four of these oscillators, all running independently.
+/// Our `Synth` has one of these oscillators per channel, all running
+/// independently.
 struct Oscillator {
     /// Which waveform we're playing.
-    waveform: &'static [i8; 256],
+    waveform: Waveform,
+    /// How we read the waveform table. This is the same for every channel.
+    interpolation: Interpolation,
+    /// Do we calculate sawtooth, square and pulse waves with PolyBLEP
+    /// instead? This is the same for every channel.
+    band_limited: bool,
+    /// How much of each cycle a `Waveform::Pulse` spends high, out of 256.
+    duty_cycle: u8,
+    /// The shift register used to generate `Waveform::Noise`.
+    lfsr: u16,
     /// Calculated from the note frequency, sets how far we step through the
-    /// waveform for each sample.
-    phase_step: u16,
-    /// Sets our current position within the waveform.
-    phase_accumulator: u16,
+    /// waveform for each sample. See `Synth::frequency_to_phase_step`.
+    phase_step: u32,
+    /// Sets our current position within the waveform. The top 8 bits are the
+    /// index into the 256 entry wavetable.
+    phase_accumulator: u32,
+    /// How far we actually stepped on the last sample, once vibrato and
+    /// arpeggios have bent `phase_step`.
+    current_step: u32,
+    /// How many samples `Synth::slide_to` takes to reach the new note.
+    portamento: u32,
+    /// Where we are during a slide, as a `phase_step` with 16 extra
+    /// fractional bits.
+    glide_level: u64,
+    /// How much `glide_level` changes each sample during a slide.
+    glide_step: i64,
+    /// The `phase_step` we're sliding towards.
+    glide_target: u32,
+    /// How many samples are left in the current slide.
+    glide_remaining: u32,
     /// Controls the volume of this channel relative to the others.
     volume: u8,
+    /// Shapes the volume of each note over time.
+    envelope: EnvelopeGenerator,
+    /// Wobbles the pitch and/or the volume.
+    lfo: LfoGenerator,
+    /// Our FM settings.
+    fm: Fm,
+    /// Sets the current position of the FM modulator.
+    fm_phase_accumulator: u32,
+    /// How far the FM modulator is currently pushing our phase.
+    fm_offset: u32,
+    /// The channel whose waveform we multiply ours by, if any.
+    ring_source: Option<usize>,
+    /// The channel which restarts our waveform whenever its waveform
+    /// restarts, if any.
+    sync_source: Option<usize>,
+    /// Is this channel routed through the filter?
+    filtered: bool,
+    /// Cycles the pitch through some nearby notes.
+    arpeggio: Arpeggio,
+    /// Which note of the arpeggio we're on.
+    arpeggio_idx: usize,
+    /// How many samples are left before we move to the next note of the
+    /// arpeggio.
+    arpeggio_remaining: u32,
+    /// The gain applied to this channel in the left output, where 256 is
+    /// unity.
+    pan_left: i32,
+    /// The gain applied to this channel in the right output, where 256 is
+    /// unity.
+    pan_right: i32,
+}
+
+/// An ADSR envelope, applied to the volume of a channel. The attack, decay
+/// and release times are in milliseconds. The sustain level is relative to
+/// the channel volume, where 255 is the full channel volume.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Envelope {
+    /// How long it takes to go from silence to full volume after a note-on.
+    pub attack: u16,
+    /// How long it takes to go from full volume down to the sustain level.
+    pub decay: u16,
+    /// The level held for as long as the note is on.
+    pub sustain: u8,
+    /// How long it takes to go from the current level down to silence after a
+    /// note-off.
+    pub release: u16,
+}
+
+/// Which part of the envelope a channel is currently in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum EnvelopeStage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Idle,
+}
+
+/// Tracks where an `Oscillator` is within its `Envelope`. The level is a
+/// fixed-point value where `ENVELOPE_FULL` is unity gain.
+struct EnvelopeGenerator {
+    stage: EnvelopeStage,
+    level: u32,
+    attack_step: u32,
+    decay_step: u32,
+    sustain_level: u32,
+    release_step: u32,
+    /// Kept so we can work out the release step at note-off time, when we
+    /// know what level we're releasing from.
+    release_ms: u16,
+}
+
+/// The most notes an `Arpeggio` can cycle through.
+pub const MAX_ARPEGGIO_LEN: usize = 8;
+
+/// An arpeggio, which rapidly cycles a channel through the note being played
+/// and some other notes a few semitones away, to fake a chord.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Arpeggio {
+    /// How far each note in the cycle is from the note being played, in
+    /// semitones. Only the first `len` are used. You probably want the first
+    /// one to be zero. These are always equal-tempered semitones, whatever
+    /// the `Tuning`.
+    pub offsets: [i8; MAX_ARPEGGIO_LEN],
+    /// How many notes there are in the cycle.
+    pub len: u8,
+    /// How many samples each note in the cycle lasts for.
+    pub step_samples: u32,
+}
+
+/// Two-operator FM synthesis settings for a channel. A hidden sine wave
+/// modulator, running at a multiple of the note frequency, pushes the phase
+/// of the channel's waveform back and forth. This gives everything from
+/// bells and electric pianos to metallic clangs, with no extra wavetables.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fm {
+    /// The modulator runs at `numerator / denominator` times the note
+    /// frequency. Whole-number ratios sound harmonic; others sound clangy.
+    pub numerator: u8,
+    /// See `numerator`. Zero is treated as one.
+    pub denominator: u8,
+    /// How far the modulator pushes the phase, where 64 is about one whole
+    /// cycle either way (a modulation index of about 6.3). Zero turns FM off.
+    pub index: u8,
+}
+
+/// A low frequency oscillator, which can wobble the pitch of a channel
+/// (vibrato) and/or its volume (tremolo).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lfo {
+    /// How fast the wobble goes.
+    pub rate: Frequency,
+    /// How far the pitch moves either side of the note, in cents.
+    pub vibrato_depth: u16,
+    /// How far the volume dips, where 255 takes it all the way down to
+    /// silence.
+    pub tremolo_depth: u8,
+    /// How long after the start of each note before the wobble begins, in
+    /// milliseconds.
+    pub delay: u16,
+}
+
+/// Tracks where an `Oscillator` is within its `Lfo`.
+struct LfoGenerator {
+    /// Sets our current position within the LFO waveform. This has 24 bits
+    /// of fraction, as LFOs are slow.
+    phase_accumulator: u32,
+    phase_step: u32,
+    vibrato_depth: i32,
+    tremolo_depth: i32,
+    delay_samples: u32,
+    delay_remaining: u32,
 }
 
+/// Envelope levels have 16 fractional bits, so the top bits give a gain
+/// where 256 is unity.
+const ENVELOPE_FULL: u32 = 256 << 16;
+
+/// The fractional part of `2^(n/12)` for each semitone `n` in an octave, with
+/// 16 fractional bits.
+const SEMITONE_RATIOS: [u16; 12] = [
+    0, 3897, 8026, 12400, 17034, 21944, 27146, 32657, 38496, 44682, 51236, 58179,
+];
+
+/// The fractional part of `2^(n/1200)` for each cent `n` in a semitone, with
+/// 16 fractional bits.
+const CENT_RATIOS: [u16; 100] = [
+    0, 38, 76, 114, 152, 190, 228, 266, 304, 342, 380, 418, 456, 494, 532, 570, 608, 647, 685, 723,
+    761, 800, 838, 876, 915, 953, 992, 1030, 1069, 1107, 1146, 1184, 1223, 1261, 1300, 1338, 1377,
+    1416, 1454, 1493, 1532, 1571, 1609, 1648, 1687, 1726, 1765, 1804, 1842, 1881, 1920, 1959, 1998,
+    2037, 2076, 2115, 2155, 2194, 2233, 2272, 2311, 2350, 2390, 2429, 2468, 2507, 2547, 2586, 2625,
+    2665, 2704, 2744, 2783, 2823, 2862, 2902, 2941, 2981, 3020, 3060, 3099, 3139, 3179, 3219, 3258,
+    3298, 3338, 3378, 3417, 3457, 3497, 3537, 3577, 3617, 3657, 3697, 3737, 3777, 3817, 3857,
+];
+
+/// The master level, with 16 fractional bits, which gives unity gain.
+const MASTER_FULL: i32 = 256 << 16;
+
+/// How many samples `Synth::render_u8` and friends mix in one go.
+const RENDER_BLOCK_LEN: usize = 32;
+
 /// A single signed 8-bit audio sample.
 #[derive(Debug)]
 pub struct Sample(i8);
 
+/// How the sum of the channels is squeezed into the range of an output
+/// sample.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClipMode {
+    /// Anything too loud is clamped to the largest sample value. This is the
+    /// default.
+    Hard,
+    /// Quiet mixes pass through almost untouched, while loud mixes are
+    /// squashed by a `tanh`-like curve that approaches, but never quite
+    /// reaches, the largest sample value.
+    Soft,
+    /// The mix is divided by the number of channels currently playing, so it
+    /// can never clip. This is louder than the other modes when few channels
+    /// are playing, but the level jumps as channels start and stop.
+    Headroom,
+}
+
+/// How the oscillators read their wavetables.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    /// Use the nearest table entry. This is the cheapest, and the default,
+    /// but low notes sound stepped and high notes alias.
+    Nearest,
+    /// Blend the two nearest table entries, for a smoother sound at the cost
+    /// of a multiply per channel per sample.
+    Linear,
+}
+
+/// What the filter lets through.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterMode {
+    /// Cuts frequencies above the cutoff, for a darker, muffled sound.
+    LowPass,
+    /// Cuts frequencies either side of the cutoff, for a thin, nasal sound.
+    BandPass,
+    /// Cuts frequencies below the cutoff, for a bright, tinny sound.
+    HighPass,
+}
+
+/// A resonant filter, like the one in the SID. Channels are routed through
+/// it with `Synth::set_filter_routing`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    pub mode: FilterMode,
+    /// Where the filter starts to cut. This is limited to a sixth of the
+    /// sample rate, which keeps the filter stable.
+    pub cutoff: Frequency,
+    /// How much the filter boosts frequencies around the cutoff. At 255 it
+    /// rings like a bell.
+    pub resonance: u8,
+}
+
+/// A Chamberlin state variable filter, in fixed point. The coefficients have
+/// 16 fractional bits.
+struct StateVariableFilter {
+    mode: FilterMode,
+    /// Twice the sine of the cutoff, as a fraction of the sample rate.
+    frequency: i64,
+    /// The damping, which is the inverse of the resonance.
+    damping: i64,
+    low: i64,
+    band: i64,
+}
+
+/// A single signed 16-bit audio sample.
+#[derive(Debug)]
+pub struct Sample16(i16);
+
 /// A frequency in centi-hertz.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Frequency(u32);
 
+/// The twelve notes in an octave, ignoring which octave they're in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum PitchClass {
+    C,
+    CsDb,
+    D,
+    DsEb,
+    E,
+    F,
+    FsGb,
+    G,
+    GsAb,
+    A,
+    AsBb,
+    B,
+}
+
+/// How the twelve notes of an octave are spaced out.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Temperament {
+    /// Every semitone is exactly 100 cents. Every key sounds the same, and
+    /// every interval is very slightly out of tune.
+    Equal,
+    /// Five-limit just intonation, where the intervals from the key note are
+    /// small whole number ratios (5/4 for a major third, 3/2 for a fifth and
+    /// so on). Chords in the key ring out, but other keys sound sour.
+    Just,
+    /// Every interval from the key note is built from perfect (3/2) fifths.
+    /// Fifths and fourths are pure, but thirds are quite wide.
+    Pythagorean,
+    /// Your own offset, in cents, from equal temperament for each note of the
+    /// octave, starting at the key note.
+    Custom([i16; 12]),
+}
+
+/// Sets which frequency each `Note` plays at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tuning {
+    /// The frequency of A4. This is 440 Hz as standard, but 442 Hz and 432 Hz
+    /// are also popular. The temperament is shifted so that A4 stays on this
+    /// frequency, whatever the key.
+    pub reference: Frequency,
+    pub temperament: Temperament,
+    /// Which note the temperament is built on. This doesn't matter for
+    /// `Temperament::Equal`.
+    pub key: PitchClass,
+}
+
+/// Something which can be played on a channel.
+pub trait Pitch {
+    /// Work out the frequency to play at, given the synthesiser's tuning.
+    fn frequency(self, tuning: &Tuning) -> Frequency;
+}
+
 /// Notes on an piano keyboard, where A4 = 440 Hz.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
@@ -206,21 +542,64 @@ const SQUARE_256: [i8; 256] = [
     -127, -127, -127, -127, -127, -127, -127, -127, -127, -127, -127, -127,
 ];
 
-/// Static white noise sampled as 256 signed 8-bit values.
-const NOISE_256: [i8; 256] = [
-    -63, -8, -26, -77, -14, 44, -42, 96, 114, -103, 78, 117, -127, 90, 94, 72, 75, -1, 81, -60, 27,
-    -90, -115, 49, 126, -123, -84, 89, -42, 84, 73, -123, -51, -90, 77, 105, -65, -57, -52, -128,
-    -101, 32, 14, 126, -43, -101, 13, 20, 28, 92, -12, -94, 31, -103, -68, 76, 87, -92, 3, -60, 88,
-    -99, 38, 8, 29, 87, -84, 43, -32, 61, -110, 41, 6, 2, -26, -102, -63, 100, 39, 13, -119, -55,
-    -99, -84, 110, 5, -11, 58, -95, -35, -116, 115, 25, 78, -127, -5, -111, -58, -121, -128, 9,
-    -98, 12, 97, 99, 117, 115, 50, 106, 6, -122, 89, -83, -7, -19, -87, -119, -108, -85, -105, 91,
-    -84, -109, 64, -61, 58, -84, 49, -83, -52, 36, -84, 13, 68, 92, -74, 39, -3, 23, -23, 56, 18,
-    53, -20, 66, 54, 16, -91, 30, -90, -54, -106, 94, 56, -35, -41, 121, -66, 75, 46, 120, -105,
-    -48, -34, 120, 58, -48, 18, -87, -127, -32, -38, 12, 115, -62, 22, 87, 93, -51, 30, 29, -1,
-    109, -13, -61, 93, -79, 31, 0, 117, -86, -79, 66, -52, 81, 119, 5, -85, 90, 125, 78, 123, -75,
-    -70, -1, 122, -43, -55, 96, -106, 39, 50, -74, -125, -96, 57, -35, -29, 17, 30, 5, -48, -24,
-    55, -102, -95, -85, 44, -84, -52, 4, -77, 98, 25, -117, 46, -75, 39, -52, 102, 113, 98, -88,
-    -25, -121, 83, -9, 85, -35, 49, -89, -116, 66, -95, 99, -33,
+/// A triangle wave sampled as 256 signed 8-bit values.
+const TRIANGLE_256: [i8; 256] = [
+    0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22, 24, 26, 28, 30, 32, 34, 36, 38, 40, 42, 44, 46, 48,
+    50, 52, 54, 56, 58, 60, 62, 64, 65, 67, 69, 71, 73, 75, 77, 79, 81, 83, 85, 87, 89, 91, 93, 95,
+    97, 99, 101, 103, 105, 107, 109, 111, 113, 115, 117, 119, 121, 123, 125, 127, 125, 123, 121,
+    119, 117, 115, 113, 111, 109, 107, 105, 103, 101, 99, 97, 95, 93, 91, 89, 87, 85, 83, 81, 79,
+    77, 75, 73, 71, 69, 67, 65, 64, 62, 60, 58, 56, 54, 52, 50, 48, 46, 44, 42, 40, 38, 36, 34, 32,
+    30, 28, 26, 24, 22, 20, 18, 16, 14, 12, 10, 8, 6, 4, 2, 0, -2, -4, -6, -8, -10, -12, -14, -16,
+    -18, -20, -22, -24, -26, -28, -30, -32, -34, -36, -38, -40, -42, -44, -46, -48, -50, -52, -54,
+    -56, -58, -60, -62, -64, -65, -67, -69, -71, -73, -75, -77, -79, -81, -83, -85, -87, -89, -91,
+    -93, -95, -97, -99, -101, -103, -105, -107, -109, -111, -113, -115, -117, -119, -121, -123,
+    -125, -127, -125, -123, -121, -119, -117, -115, -113, -111, -109, -107, -105, -103, -101, -99,
+    -97, -95, -93, -91, -89, -87, -85, -83, -81, -79, -77, -75, -73, -71, -69, -67, -65, -64, -62,
+    -60, -58, -56, -54, -52, -50, -48, -46, -44, -42, -40, -38, -36, -34, -32, -30, -28, -26, -24,
+    -22, -20, -18, -16, -14, -12, -10, -8, -6, -4, -2,
+];
+
+/// A triangle wave with only 16 distinct levels, like the one produced by the
+/// 4-bit DAC in the NES APU, sampled as 256 signed 8-bit values.
+const NES_TRIANGLE_256: [i8; 256] = [
+    8, 8, 8, 8, 8, 8, 8, 8, 25, 25, 25, 25, 25, 25, 25, 25, 42, 42, 42, 42, 42, 42, 42, 42, 59, 59,
+    59, 59, 59, 59, 59, 59, 76, 76, 76, 76, 76, 76, 76, 76, 93, 93, 93, 93, 93, 93, 93, 93, 110,
+    110, 110, 110, 110, 110, 110, 110, 127, 127, 127, 127, 127, 127, 127, 127, 127, 127, 127, 127,
+    127, 127, 127, 127, 110, 110, 110, 110, 110, 110, 110, 110, 93, 93, 93, 93, 93, 93, 93, 93, 76,
+    76, 76, 76, 76, 76, 76, 76, 59, 59, 59, 59, 59, 59, 59, 59, 42, 42, 42, 42, 42, 42, 42, 42, 25,
+    25, 25, 25, 25, 25, 25, 25, 8, 8, 8, 8, 8, 8, 8, 8, -8, -8, -8, -8, -8, -8, -8, -8, -25, -25,
+    -25, -25, -25, -25, -25, -25, -42, -42, -42, -42, -42, -42, -42, -42, -59, -59, -59, -59, -59,
+    -59, -59, -59, -76, -76, -76, -76, -76, -76, -76, -76, -93, -93, -93, -93, -93, -93, -93, -93,
+    -110, -110, -110, -110, -110, -110, -110, -110, -127, -127, -127, -127, -127, -127, -127, -127,
+    -127, -127, -127, -127, -127, -127, -127, -127, -110, -110, -110, -110, -110, -110, -110, -110,
+    -93, -93, -93, -93, -93, -93, -93, -93, -76, -76, -76, -76, -76, -76, -76, -76, -59, -59, -59,
+    -59, -59, -59, -59, -59, -42, -42, -42, -42, -42, -42, -42, -42, -25, -25, -25, -25, -25, -25,
+    -25, -25, -8, -8, -8, -8, -8, -8, -8, -8,
+];
+
+/// The soft clipping curve, `32767 * tanh(x / 32768)`, sampled every 512
+/// steps of `x` from 0 up to 131072, which is where it has flattened out.
+const SOFT_CLIP_257: [u16; 257] = [
+    0, 512, 1024, 1535, 2045, 2555, 3063, 3570, 4075, 4578, 5079, 5577, 6073, 6566, 7056, 7542,
+    8025, 8505, 8980, 9452, 9919, 10382, 10840, 11294, 11742, 12186, 12625, 13058, 13486, 13908,
+    14325, 14737, 15142, 15542, 15936, 16324, 16706, 17081, 17451, 17815, 18173, 18524, 18869,
+    19208, 19541, 19868, 20189, 20503, 20812, 21114, 21411, 21701, 21986, 22264, 22537, 22804,
+    23065, 23320, 23570, 23815, 24053, 24287, 24515, 24738, 24955, 25168, 25375, 25578, 25775,
+    25968, 26156, 26339, 26518, 26693, 26863, 27029, 27190, 27348, 27501, 27650, 27796, 27937,
+    28075, 28210, 28340, 28468, 28592, 28712, 28829, 28943, 29054, 29162, 29267, 29369, 29469,
+    29565, 29659, 29750, 29839, 29925, 30009, 30090, 30169, 30246, 30321, 30393, 30464, 30532,
+    30599, 30663, 30726, 30787, 30846, 30903, 30959, 31013, 31066, 31117, 31166, 31215, 31261,
+    31306, 31350, 31393, 31435, 31475, 31514, 31552, 31588, 31624, 31658, 31692, 31725, 31756,
+    31787, 31817, 31845, 31873, 31900, 31927, 31952, 31977, 32001, 32024, 32047, 32069, 32090,
+    32111, 32131, 32150, 32169, 32187, 32205, 32222, 32239, 32255, 32270, 32286, 32300, 32315,
+    32328, 32342, 32355, 32367, 32380, 32391, 32403, 32414, 32425, 32435, 32446, 32455, 32465,
+    32474, 32483, 32492, 32500, 32508, 32516, 32524, 32531, 32539, 32546, 32553, 32559, 32565,
+    32572, 32578, 32583, 32589, 32595, 32600, 32605, 32610, 32615, 32619, 32624, 32628, 32633,
+    32637, 32641, 32645, 32648, 32652, 32656, 32659, 32662, 32666, 32669, 32672, 32675, 32677,
+    32680, 32683, 32685, 32688, 32690, 32693, 32695, 32697, 32699, 32701, 32703, 32705, 32707,
+    32709, 32711, 32713, 32714, 32716, 32717, 32719, 32720, 32722, 32723, 32725, 32726, 32727,
+    32728, 32730, 32731, 32732, 32733, 32734, 32735, 32736, 32737, 32738, 32739, 32740, 32740,
+    32741, 32742, 32743, 32744, 32744, 32745,
 ];
 
 /// A sawtooth wave sampled as 256 signed 8-bit values.
@@ -240,79 +619,681 @@ const SAWTOOTH_256: [i8; 256] = [
     118, 119, 120, 121, 122, 123, 124, 125, 126, 127,
 ];
 
-impl Synth {
-    pub const fn new(sample_rate: u32) -> Synth {
+/// Builds a single-cycle waveform, suitable for `Waveform::Custom`, by summing
+/// sine wave harmonics. The first entry in `amplitudes` is the level of the
+/// fundamental, the second is the level of the second harmonic, and so on.
+/// Negative levels invert the phase of that harmonic. The result is scaled so
+/// the peak just fits in an `i8`.
+///
+/// This is a `const fn`, so you can build your tables into a `static` at
+/// compile time and they will live in flash.
+///
+/// Harmonics above the 127th cannot be represented in a 256 entry table and
+/// will alias.
+pub const fn wavetable_from_harmonics(amplitudes: &[i8]) -> [i8; 256] {
+    let mut sums = [0i32; 256];
+    let mut peak = 0;
+    let mut i = 0;
+    while i < 256 {
+        let mut harmonic = 0;
+        while harmonic < amplitudes.len() {
+            let offset = (i * (harmonic + 1)) & 0xFF;
+            sums[i] += SINE_256[offset] as i32 * amplitudes[harmonic] as i32;
+            harmonic += 1;
+        }
+        let magnitude = if sums[i] < 0 { -sums[i] } else { sums[i] };
+        if magnitude > peak {
+            peak = magnitude;
+        }
+        i += 1;
+    }
+    let mut table = [0i8; 256];
+    if peak == 0 {
+        return table;
+    }
+    i = 0;
+    while i < 256 {
+        table[i] = (sums[i] * 127 / peak) as i8;
+        i += 1;
+    }
+    table
+}
+
+impl<const N: usize> Channel<N> {
+    /// Select a channel by index, if a `Synth` with `N` channels has that
+    /// channel.
+    pub const fn new(index: usize) -> Option<Channel<N>> {
+        if index < N {
+            Some(Channel(index))
+        } else {
+            None
+        }
+    }
+
+    /// Get the index of this channel.
+    pub const fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl<const N: usize> Synth<N> {
+    pub const fn new(sample_rate: u32) -> Synth<N> {
         Synth {
             sample_rate,
-            channels: [
-                Oscillator {
-                    phase_accumulator: 0,
-                    phase_step: 0,
-                    volume: 0,
-                    waveform: &SINE_256,
-                },
-                Oscillator {
-                    phase_accumulator: 0,
-                    phase_step: 0,
-                    volume: 0,
-                    waveform: &SINE_256,
-                },
-                Oscillator {
-                    phase_accumulator: 0,
-                    phase_step: 0,
-                    volume: 0,
-                    waveform: &SINE_256,
-                },
-            ],
+            channels: [Oscillator::OFF; N],
+            clip_mode: ClipMode::Hard,
+            master_level: MASTER_FULL,
+            fade_step: 0,
+            fade_target: MASTER_FULL,
+            fade_remaining: 0,
+            filter_in_use: false,
+            filter_left: StateVariableFilter::OFF,
+            filter_right: StateVariableFilter::OFF,
+            tuning: Tuning::STANDARD,
         }
     }
 
+    /// Get the number of channels this synthesiser has.
+    pub const fn num_channels(&self) -> usize {
+        N
+    }
+
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
 
-    pub fn play<T>(&mut self, channel: Channel, note: T, volume: u8, waveform: Waveform)
+    /// Choose how the channels are squeezed into the output when they're all
+    /// loud at once.
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.clip_mode = clip_mode;
+    }
+
+    /// Turn band-limiting on or off for every channel. Naive sawtooth, square
+    /// and pulse waves have harmonics well above half the sample rate, which
+    /// fold back down as harsh, out of tune aliasing on high notes. With this
+    /// on (it's off by default), those waves are calculated with PolyBLEP
+    /// corrections around each jump, which removes most of the aliasing at
+    /// the cost of a few multiplies and a divide per channel per sample.
+    pub fn set_band_limited(&mut self, band_limited: bool) {
+        for osc in &mut self.channels {
+            osc.band_limited = band_limited;
+        }
+    }
+
+    /// Choose how every channel reads its wavetable. This doesn't affect
+    /// noise or `Waveform::Pulse`, which aren't read from a table.
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        for osc in &mut self.channels {
+            osc.interpolation = interpolation;
+        }
+    }
+
+    /// Choose how notes are tuned. This only affects notes played after you
+    /// call it.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    /// Get the current tuning.
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    /// Start playing a note. This is the same as `note_on`.
+    pub fn play<T>(&mut self, channel: Channel<N>, note: T, volume: u8, waveform: Waveform)
+    where
+        T: Pitch,
+    {
+        self.note_on(channel, note, volume, waveform);
+    }
+
+    /// Start playing a note, from the start of the channel's envelope. If the
+    /// channel is still sounding (say, it's in its release), the level isn't
+    /// reset to zero first, so the attack rises from wherever it was. This
+    /// avoids a click when a note is retriggered.
+    pub fn note_on<T>(&mut self, channel: Channel<N>, note: T, volume: u8, waveform: Waveform)
     where
-        T: Into<Frequency>,
+        T: Pitch,
     {
-        let step = self.frequency_to_phase_step(note.into());
-        let ch = &mut self.channels[channel as usize];
+        let step = self.frequency_to_phase_step(note.frequency(&self.tuning));
+        let ch = &mut self.channels[channel.0];
         ch.phase_accumulator = 0;
         ch.phase_step = step;
+        ch.glide_remaining = 0;
         ch.volume = volume;
-        ch.waveform = match waveform {
-            Waveform::Sine => &SINE_256,
-            Waveform::Noise => &NOISE_256,
-            Waveform::Sawtooth => &SAWTOOTH_256,
-            Waveform::Square => &SQUARE_256,
-        };
+        ch.waveform = waveform;
+        ch.envelope.stage = EnvelopeStage::Attack;
+        ch.fm_phase_accumulator = 0;
+        ch.fm_offset = 0;
+        ch.lfo.phase_accumulator = 0;
+        ch.lfo.delay_remaining = ch.lfo.delay_samples;
+        ch.arpeggio_idx = 0;
+        ch.arpeggio_remaining = ch.arpeggio.step_samples;
+    }
+
+    /// Let the note on a channel fade away, according to the release time of
+    /// the channel's envelope.
+    pub fn note_off(&mut self, channel: Channel<N>) {
+        let ch = &self.channels[channel.0];
+        let release_step = self.envelope_step(ch.envelope.level, ch.envelope.release_ms);
+        let ch = &mut self.channels[channel.0];
+        ch.envelope.release_step = release_step;
+        ch.envelope.stage = EnvelopeStage::Release;
     }
 
-    pub fn off(&mut self, channel: Channel) {
-        let ch = &mut self.channels[channel as usize];
+    /// Silence a channel immediately, ignoring the envelope.
+    pub fn off(&mut self, channel: Channel<N>) {
+        let ch = &mut self.channels[channel.0];
         ch.volume = 0;
         ch.phase_accumulator = 0;
         ch.phase_step = 0;
+        ch.glide_remaining = 0;
+        ch.envelope.stage = EnvelopeStage::Idle;
+        ch.envelope.level = 0;
+    }
+
+    /// Change the pitch of a channel straight away. Unlike `play`, the note
+    /// carries on from where it is, without restarting the waveform or the
+    /// envelope, so there is no click.
+    pub fn set_frequency<T>(&mut self, channel: Channel<N>, note: T)
+    where
+        T: Pitch,
+    {
+        let step = self.frequency_to_phase_step(note.frequency(&self.tuning));
+        let ch = &mut self.channels[channel.0];
+        ch.phase_step = step;
+        ch.glide_remaining = 0;
+    }
+
+    /// Change the volume of a channel, without restarting the note.
+    pub fn set_volume(&mut self, channel: Channel<N>, volume: u8) {
+        self.channels[channel.0].volume = volume;
+    }
+
+    /// Change the waveform of a channel, without restarting the note. We
+    /// carry on from the same point in the cycle in the new waveform.
+    pub fn set_waveform(&mut self, channel: Channel<N>, waveform: Waveform) {
+        self.channels[channel.0].waveform = waveform;
+    }
+
+    /// Set how many samples `slide_to` takes to move this channel from one
+    /// note to the next. Zero (the default) means it jumps straight there.
+    pub fn set_portamento(&mut self, channel: Channel<N>, samples: u32) {
+        self.channels[channel.0].portamento = samples;
+    }
+
+    /// Slide the pitch of a channel to a new note, over the time set with
+    /// `set_portamento`. Unlike `play`, the note carries on from where it is,
+    /// without restarting the waveform or the envelope.
+    pub fn slide_to<T>(&mut self, channel: Channel<N>, note: T)
+    where
+        T: Pitch,
+    {
+        let target = self.frequency_to_phase_step(note.frequency(&self.tuning));
+        let ch = &mut self.channels[channel.0];
+        if ch.portamento == 0 {
+            ch.phase_step = target;
+            ch.glide_remaining = 0;
+        } else {
+            let distance = (i64::from(target) - i64::from(ch.phase_step)) << 16;
+            ch.glide_level = u64::from(ch.phase_step) << 16;
+            ch.glide_step = distance / i64::from(ch.portamento);
+            ch.glide_target = target;
+            ch.glide_remaining = ch.portamento;
+        }
+    }
+
+    /// Set up the filter. Only channels routed with `set_filter_routing` go
+    /// through it. You can call this as often as you like (say, once a
+    /// frame) to sweep the cutoff.
+    pub fn set_filter(&mut self, filter: Filter) {
+        // The cutoff as an angle, with 16 fractional bits:
+        // `pi * cutoff / sample_rate`
+        const PI: i64 = 205_887;
+        let cutoff =
+            i64::from(filter.cutoff.centi_hertz()).min(i64::from(self.sample_rate) * 100 / 6);
+        let angle = PI * cutoff / (i64::from(self.sample_rate) * 100);
+        // sin(x) ~= x - x^3/6 + x^5/120, which is plenty accurate enough up to
+        // pi / 6.
+        let angle_cubed = (((angle * angle) >> 16) * angle) >> 16;
+        let angle_fifth = (((angle_cubed * angle) >> 16) * angle) >> 16;
+        let frequency = 2 * (angle - angle_cubed / 6 + angle_fifth / 120);
+        // Damping goes from root-two (no resonance) down to 0.05 (lots).
+        const MAX_DAMPING: i64 = 92_682;
+        const MIN_DAMPING: i64 = 3_277;
+        let damping = MAX_DAMPING - (MAX_DAMPING - MIN_DAMPING) * i64::from(filter.resonance) / 255;
+        for svf in &mut [&mut self.filter_left, &mut self.filter_right] {
+            svf.mode = filter.mode;
+            svf.frequency = frequency;
+            svf.damping = damping;
+        }
+    }
+
+    /// Route this channel through the filter (or not). By default, no
+    /// channels are filtered.
+    pub fn set_filter_routing(&mut self, channel: Channel<N>, filtered: bool) {
+        self.channels[channel.0].filtered = filtered;
+        self.filter_in_use = self.channels.iter().any(|osc| osc.filtered);
+    }
+
+    /// Set the duty cycle used when this channel plays `Waveform::Pulse`, as
+    /// a fraction of 256. So 128 gives a square wave, while 32 and 64 give the
+    /// thinner 12.5% and 25% pulses. This takes effect immediately, so it can
+    /// be swept while a note is playing.
+    pub fn set_duty_cycle(&mut self, channel: Channel<N>, duty_cycle: u8) {
+        self.channels[channel.0].duty_cycle = duty_cycle;
+    }
+
+    /// Set where this channel sits in the stereo image, from 0 (hard left)
+    /// through 128 (centre) to 255 (hard right). This only affects
+    /// `next_stereo` and friends.
+    pub fn set_pan(&mut self, channel: Channel<N>, pan: u8) {
+        let ch = &mut self.channels[channel.0];
+        // Centre is full volume in both speakers, so a centred channel is as
+        // loud as it is in mono. Moving away from the centre only ever turns
+        // the other side down.
+        ch.pan_left = if pan <= 128 {
+            256
+        } else {
+            i32::from(255 - pan) * 2
+        };
+        ch.pan_right = if pan >= 128 { 256 } else { i32::from(pan) * 2 };
+    }
+
+    /// Set the arpeggio used by this channel. This takes effect straight away,
+    /// and the cycle starts again from the top with every new note. Use
+    /// `Arpeggio::NONE` to turn it off again.
+    pub fn set_arpeggio(&mut self, channel: Channel<N>, arpeggio: Arpeggio) {
+        self.channels[channel.0].arpeggio = arpeggio;
+    }
+
+    /// Set the FM settings for this channel. Use `Fm::NONE` to turn FM off
+    /// again. FM works best with `Waveform::Sine`, but you can use it with any
+    /// waveform.
+    pub fn set_fm(&mut self, channel: Channel<N>, fm: Fm) {
+        let ch = &mut self.channels[channel.0];
+        ch.fm = fm;
+        ch.fm_offset = 0;
+    }
+
+    /// Ring modulate this channel with another, so our waveform is multiplied
+    /// by theirs. This gives clangy, bell-like sounds, especially when the two
+    /// notes aren't harmonically related. Pass `None` to turn it off again.
+    pub fn set_ring_mod(&mut self, channel: Channel<N>, source: Option<Channel<N>>) {
+        self.channels[channel.0].ring_source = source.map(|source| source.0);
+    }
+
+    /// Hard sync this channel to another, so our waveform restarts every time
+    /// theirs does. Sweeping the pitch of this channel then changes its tone
+    /// rather than its pitch. Pass `None` to turn it off again.
+    pub fn set_sync(&mut self, channel: Channel<N>, source: Option<Channel<N>>) {
+        self.channels[channel.0].sync_source = source.map(|source| source.0);
+    }
+
+    /// Set the LFO for this channel. By default, channels use `Lfo::NONE`.
+    /// The rate and depths take effect straight away, even on a note that is
+    /// already playing, but the delay only applies from the next `note_on`.
+    pub fn set_lfo(&mut self, channel: Channel<N>, lfo: Lfo) {
+        let phase_step = self.frequency_to_phase_step(lfo.rate);
+        let delay_samples = (u64::from(lfo.delay) * u64::from(self.sample_rate) / 1000) as u32;
+        let gen = &mut self.channels[channel.0].lfo;
+        gen.phase_step = phase_step;
+        gen.vibrato_depth = i32::from(lfo.vibrato_depth);
+        // Scale so that 255 is all the way down
+        let tremolo_depth = i32::from(lfo.tremolo_depth);
+        gen.tremolo_depth = tremolo_depth + (tremolo_depth >> 7);
+        gen.delay_samples = delay_samples;
+    }
+
+    /// Set the envelope for this channel. By default, channels use
+    /// `Envelope::NONE`. This takes effect straight away, so a note that is
+    /// already playing carries on with the new attack, decay and release
+    /// times. A note that has already reached its sustain stays at the old
+    /// sustain level until it is released.
+    pub fn set_envelope(&mut self, channel: Channel<N>, envelope: Envelope) {
+        let sustain_level = (u64::from(ENVELOPE_FULL) * u64::from(envelope.sustain) / 255) as u32;
+        let attack_step = self.envelope_step(ENVELOPE_FULL, envelope.attack);
+        let decay_step = self.envelope_step(ENVELOPE_FULL - sustain_level, envelope.decay);
+        let env = &mut self.channels[channel.0].envelope;
+        env.attack_step = attack_step;
+        env.decay_step = decay_step;
+        env.sustain_level = sustain_level;
+        env.release_ms = envelope.release;
+    }
+
+    /// Work out how much an envelope level must change each sample to
+    /// travel `range` in `duration_ms` milliseconds.
+    fn envelope_step(&self, range: u32, duration_ms: u16) -> u32 {
+        let samples = (u64::from(duration_ms) * u64::from(self.sample_rate)) / 1000;
+        match u64::from(range).checked_div(samples) {
+            Some(step) => (step as u32).max(1),
+            // Zero duration, so jump there in one go
+            None => range.max(1),
+        }
+    }
+
+    /// Set the volume of the whole mix, where `MAX_VOLUME` (the default)
+    /// leaves the channels as they are. This cancels any fade in progress.
+    pub fn set_master_volume(&mut self, volume: u8) {
+        self.master_level = Self::master_level(volume);
+        self.fade_target = self.master_level;
+        self.fade_remaining = 0;
+    }
+
+    /// Get the current volume of the whole mix. During a fade, this is how far
+    /// the fade has got.
+    pub fn master_volume(&self) -> u8 {
+        let gain = self.master_level >> 16;
+        // Undo the scaling in `master_level`
+        if gain > 128 {
+            (gain - 1) as u8
+        } else {
+            gain as u8
+        }
+    }
+
+    /// Smoothly move the master volume from where it is now to `volume`,
+    /// over the next `duration_samples` samples. Use `is_fading` to find out
+    /// when it's done.
+    pub fn fade_to(&mut self, volume: u8, duration_samples: u32) {
+        self.fade_target = Self::master_level(volume);
+        if duration_samples == 0 {
+            self.master_level = self.fade_target;
+            self.fade_remaining = 0;
+        } else {
+            let distance = i64::from(self.fade_target - self.master_level);
+            self.fade_step = (distance / i64::from(duration_samples)) as i32;
+            self.fade_remaining = duration_samples;
+        }
+    }
+
+    /// Is a fade started with `fade_to` still in progress?
+    pub fn is_fading(&self) -> bool {
+        self.fade_remaining != 0
+    }
+
+    /// Convert a volume into a master level, so that `MAX_VOLUME` is unity
+    /// gain.
+    fn master_level(volume: u8) -> i32 {
+        let volume = i32::from(volume);
+        (volume + (volume >> 7)) << 16
+    }
+
+    /// Move one sample along any fade in progress, and return the gain to
+    /// apply to the mix, where 256 is unity.
+    fn next_master_gain(&mut self) -> i32 {
+        if self.fade_remaining != 0 {
+            self.fade_remaining -= 1;
+            if self.fade_remaining == 0 {
+                // Land exactly on the target, whatever rounding we did
+                self.master_level = self.fade_target;
+            } else {
+                self.master_level += self.fade_step;
+            }
+        }
+        self.master_level >> 16
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Sample {
-        let mut accu: i32 = 0;
-        for osc in &mut self.channels {
-            osc.phase_accumulator = osc.phase_accumulator.wrapping_add(osc.phase_step);
-            let offset = osc.phase_accumulator >> 8;
-            // phase_accumulator is a u16. After >> 8 we get a u8, so this is safe.
-            let hi_res_sample = (unsafe { *osc.waveform.get_unchecked(offset as usize) } as i32)
-                * (osc.volume as i32);
-            accu += hi_res_sample;
+        self.next_i16().into()
+    }
+
+    /// Like `next`, but keeps the full resolution of the mix instead of
+    /// throwing it away to fit an 8-bit DAC.
+    pub fn next_i16(&mut self) -> Sample16 {
+        let accu = self.mix();
+        let gain = self.next_master_gain();
+        self.output((accu * gain) >> 8)
+    }
+
+    /// Like `next`, but produces a left and a right sample, with each channel
+    /// panned according to `set_pan`.
+    pub fn next_stereo(&mut self) -> (Sample, Sample) {
+        let (left, right) = self.next_stereo_i16();
+        (left.into(), right.into())
+    }
+
+    /// Like `next_i16`, but produces a left and a right sample, with each
+    /// channel panned according to `set_pan`.
+    pub fn next_stereo_i16(&mut self) -> (Sample16, Sample16) {
+        let (left, right) = self.mix_stereo();
+        let gain = self.next_master_gain();
+        (
+            self.output((left * gain) >> 8),
+            self.output((right * gain) >> 8),
+        )
+    }
+
+    /// Sum the next sample from every channel, passing the ones routed to
+    /// the filter through the filter.
+    fn mix(&mut self) -> i32 {
+        let mut direct: i32 = 0;
+        let mut filtered: i32 = 0;
+        if self.is_linked() {
+            let samples = self.next_channels();
+            for (osc, sample) in self.channels.iter().zip(samples.iter()) {
+                if osc.filtered {
+                    filtered += sample;
+                } else {
+                    direct += sample;
+                }
+            }
+        } else {
+            for osc in &mut self.channels {
+                if osc.filtered {
+                    filtered += osc.next();
+                } else {
+                    direct += osc.next();
+                }
+            }
+        }
+        if self.filter_in_use {
+            direct + self.filter_left.process(filtered)
+        } else {
+            direct
+        }
+    }
+
+    /// Work out the next sample for every channel, allowing for any ring
+    /// modulation and hard sync between channels.
+    fn next_channels(&mut self) -> [i32; N] {
+        let mut gains = [0i32; N];
+        let mut wrapped = [false; N];
+        for (idx, osc) in self.channels.iter_mut().enumerate() {
+            let (gain, wrap) = osc.step();
+            gains[idx] = gain;
+            wrapped[idx] = wrap;
+        }
+        let mut raw = [0i32; N];
+        for (idx, osc) in self.channels.iter_mut().enumerate() {
+            if let Some(source) = osc.sync_source {
+                if wrapped[source] {
+                    osc.phase_accumulator = 0;
+                }
+            }
+            raw[idx] = i32::from(osc.sample());
+        }
+        let mut samples = [0i32; N];
+        for (idx, osc) in self.channels.iter().enumerate() {
+            let value = match osc.ring_source {
+                Some(source) => (raw[idx] * raw[source]) >> 7,
+                None => raw[idx],
+            };
+            samples[idx] = osc.scale(value, gains[idx]);
+        }
+        samples
+    }
+
+    /// Are any channels linked to others, with ring modulation or hard sync?
+    /// If so, we can't render one channel at a time.
+    fn is_linked(&self) -> bool {
+        self.channels
+            .iter()
+            .any(|osc| osc.ring_source.is_some() || osc.sync_source.is_some())
+    }
+
+    /// Sum the next sample from every channel, once for the left and once
+    /// for the right, passing the ones routed to the filter through the
+    /// filter.
+    fn mix_stereo(&mut self) -> (i32, i32) {
+        let samples = self.next_channels();
+        let mut left = [0i32; 2];
+        let mut right = [0i32; 2];
+        for (osc, sample) in self.channels.iter().zip(samples.iter()) {
+            let route = osc.filtered as usize;
+            left[route] += (sample * osc.pan_left) >> 8;
+            right[route] += (sample * osc.pan_right) >> 8;
+        }
+        if self.filter_in_use {
+            (
+                left[0] + self.filter_left.process(left[1]),
+                right[0] + self.filter_right.process(right[1]),
+            )
+        } else {
+            (left[0], right[0])
+        }
+    }
+
+    /// Fill a buffer with unsigned 8-bit samples, as you would get from
+    /// calling `next` once per sample and converting each one to a `u8`.
+    pub fn render_u8(&mut self, out: &mut [u8]) {
+        self.render(out, |sample| Sample::from(sample).into());
+    }
+
+    /// Fill a buffer with signed 8-bit samples, as you would get from calling
+    /// `next` once per sample and converting each one to an `i8`.
+    pub fn render_i8(&mut self, out: &mut [i8]) {
+        self.render(out, |sample| Sample::from(sample).into());
+    }
+
+    /// Fill a buffer with signed 16-bit samples, as you would get from
+    /// calling `next_i16` once per sample.
+    pub fn render_i16(&mut self, out: &mut [i16]) {
+        self.render(out, |sample| sample.into());
+    }
+
+    /// Fill a buffer with interleaved left and right unsigned 8-bit samples,
+    /// as you would get from calling `next_stereo` once per pair of samples.
+    /// If the buffer has an odd length, the last sample is left alone.
+    pub fn render_stereo_u8(&mut self, out: &mut [u8]) {
+        self.render_stereo(out, |sample| Sample::from(sample).into());
+    }
+
+    /// Fill a buffer with interleaved left and right signed 8-bit samples, as
+    /// you would get from calling `next_stereo` once per pair of samples. If
+    /// the buffer has an odd length, the last sample is left alone.
+    pub fn render_stereo_i8(&mut self, out: &mut [i8]) {
+        self.render_stereo(out, |sample| Sample::from(sample).into());
+    }
+
+    /// Fill a buffer with interleaved left and right signed 16-bit samples,
+    /// as you would get from calling `next_stereo_i16` once per pair of
+    /// samples. If the buffer has an odd length, the last sample is left
+    /// alone.
+    pub fn render_stereo_i16(&mut self, out: &mut [i16]) {
+        self.render_stereo(out, |sample| sample.into());
+    }
+
+    /// Does `render` have to work a sample at a time? Linked channels depend
+    /// on each other sample by sample. In `ClipMode::Headroom`, a channel
+    /// finishing its release part way through a block changes how the rest
+    /// of the block is scaled.
+    fn needs_per_sample(&self) -> bool {
+        let releasing = self
+            .channels
+            .iter()
+            .any(|osc| osc.envelope.stage == EnvelopeStage::Release);
+        self.is_linked() || (self.clip_mode == ClipMode::Headroom && releasing)
+    }
+
+    /// Mixes samples a block at a time. Rather than visit every channel for
+    /// every sample, we run each channel over a whole block, so the per-channel
+    /// decisions are made once per block, then convert the sums to the output
+    /// format.
+    fn render<T, F>(&mut self, out: &mut [T], convert: F)
+    where
+        F: Fn(Sample16) -> T,
+    {
+        for block in out.chunks_mut(RENDER_BLOCK_LEN) {
+            if self.needs_per_sample() {
+                for sample in block.iter_mut() {
+                    *sample = convert(self.next_i16());
+                }
+                continue;
+            }
+            let mut accu = [0i32; RENDER_BLOCK_LEN];
+            let accu = &mut accu[0..block.len()];
+            let mut filtered = [0i32; RENDER_BLOCK_LEN];
+            let filtered = &mut filtered[0..block.len()];
+            for osc in &mut self.channels {
+                if osc.filtered {
+                    osc.render(filtered);
+                } else {
+                    osc.render(accu);
+                }
+            }
+            if self.filter_in_use {
+                for (sample, filtered) in accu.iter_mut().zip(filtered.iter()) {
+                    *sample += self.filter_left.process(*filtered);
+                }
+            }
+            for (sample, accu) in block.iter_mut().zip(accu.iter()) {
+                let gain = self.next_master_gain();
+                *sample = convert(self.output((*accu * gain) >> 8));
+            }
+        }
+    }
+
+    /// Like `render`, but each channel is rendered on its own and then panned
+    /// into interleaved left and right samples.
+    fn render_stereo<T, F>(&mut self, out: &mut [T], convert: F)
+    where
+        F: Fn(Sample16) -> T,
+    {
+        for block in out.chunks_mut(RENDER_BLOCK_LEN * 2) {
+            if self.needs_per_sample() {
+                for frame in block.chunks_exact_mut(2) {
+                    let (left, right) = self.next_stereo_i16();
+                    frame[0] = convert(left);
+                    frame[1] = convert(right);
+                }
+                continue;
+            }
+            let frames = block.len() / 2;
+            // Index 0 is the direct mix, and index 1 is the mix going
+            // through the filter.
+            let mut left = [[0i32; RENDER_BLOCK_LEN]; 2];
+            let mut right = [[0i32; RENDER_BLOCK_LEN]; 2];
+            for osc in &mut self.channels {
+                let mut accu = [0i32; RENDER_BLOCK_LEN];
+                let accu = &mut accu[0..frames];
+                osc.render(accu);
+                let route = osc.filtered as usize;
+                for (idx, sample) in accu.iter().enumerate() {
+                    left[route][idx] += (sample * osc.pan_left) >> 8;
+                    right[route][idx] += (sample * osc.pan_right) >> 8;
+                }
+            }
+            if self.filter_in_use {
+                for idx in 0..frames {
+                    left[0][idx] += self.filter_left.process(left[1][idx]);
+                    right[0][idx] += self.filter_right.process(right[1][idx]);
+                }
+            }
+            for (idx, frame) in block.chunks_exact_mut(2).enumerate() {
+                let gain = self.next_master_gain();
+                frame[0] = convert(self.output((left[0][idx] * gain) >> 8));
+                frame[1] = convert(self.output((right[0][idx] * gain) >> 8));
+            }
         }
-        Self::downmix(accu)
     }
 
     /// Our waveforms are 256 samples long. This routine converts a playback
     /// frequency into an amount we increment our phase accumulator every
-    /// playback sample. The result is a 16-bit fixed-point value (8 bits
-    /// integer + 8 bits fraction). That is, we divide accumulated phase by
-    /// 256 to get the integer sample index, and carry the fraction for next
+    /// playback sample. The result is a 32-bit fixed-point value (8 bits
+    /// integer + 24 bits fraction). That is, we divide accumulated phase by
+    /// 2^24 to get the integer sample index, and carry the fraction for next
     /// time around.
     ///
     /// To play the waveform at 1 Hz, we need to upscale the 256 samples to
@@ -324,97 +1305,929 @@ impl Synth {
     /// To play the waveform at 1000 Hz, we need to upscale the 256 samples to
     /// `self.sample_rate` samples, with a phase step of 1000 * (256 / self.sample_rate).
     ///
-    /// `phase_step = note.hertz() * (256 / self.sample_rate)`
-    /// `phase_step_fp = 256 * note.hertz() * (256 / self.sample_rate)`
-    /// `phase_step_fp = 256 * note.centi_hertz() * (256 / (100 * self.sample_rate))`
-    fn frequency_to_phase_step(&self, frequency: Frequency) -> u16 {
-        // This is carefully arrange to try and avoid overflow. We should be
-        // good up to around 167.8 kHz (which is far higher than we can play).
-        let mut step = frequency.centi_hertz() * 256;
-        step /= self.sample_rate;
-        step *= 256;
-        step /= 100;
-        step as u16
+    /// `phase_step = frequency.hertz_f32() * (256 / self.sample_rate)`
+    /// `phase_step_fp = 2^24 * frequency.hertz_f32() * (256 / self.sample_rate)`
+    /// `phase_step_fp = 2^32 * frequency.centi_hertz() / (100 * self.sample_rate)`
+    fn frequency_to_phase_step(&self, frequency: Frequency) -> u32 {
+        // Anything at or above the sample rate is far too high to play, so
+        // just saturate.
+        let step = (u64::from(frequency.centi_hertz()) << 32) / (u64::from(self.sample_rate) * 100);
+        step.min(u64::from(u32::MAX)) as u32
     }
 
-    pub fn downmix(hi_res_sample: i32) -> Sample {
-        // We summed four 16-bit numbers to give an 18-bit number
-        let mut low_res_sample = hi_res_sample >> 10;
-        if low_res_sample > 127 {
-            low_res_sample = 127;
-        }
-        if low_res_sample < -128 {
-            low_res_sample = 128;
+    /// Take the sum of the channels and turn it into a 16-bit sample,
+    /// according to our `ClipMode`.
+    fn output(&self, hi_res_sample: i32) -> Sample16 {
+        match self.clip_mode {
+            ClipMode::Hard => Self::downmix_i16(hi_res_sample),
+            ClipMode::Soft => Sample16(soft_clip(hi_res_sample >> 2)),
+            ClipMode::Headroom => {
+                // Each channel gives us a 16-bit number, so dividing by the
+                // number of channels always gets us back to 16-bits.
+                let active = self.channels.iter().filter(|osc| osc.is_active()).count();
+                let sample = hi_res_sample / (active.max(1) as i32);
+                Sample16(sample.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16)
+            }
         }
+    }
+
+    pub fn downmix(hi_res_sample: i32) -> Sample {
+        // Each channel gives at most 127 * 255 (just under 15 bits), so the
+        // sum of N channels fits in 8 bits after `>> 10` as long as N <= 4.
+        // Louder mixes with more channels than that are clipped.
+        let low_res_sample = (hi_res_sample >> 10).clamp(-128, 127);
         // low_res_sample now in [-128..=127]
         Sample(low_res_sample as i8)
     }
+
+    pub fn downmix_i16(hi_res_sample: i32) -> Sample16 {
+        // Each channel gives at most 127 * 255 (just under 15 bits), so the
+        // sum of N channels fits in 16 bits after `>> 2` as long as N <= 4.
+        // Louder mixes with more channels than that are clipped. This is the
+        // same scaling as `downmix`, with an extra 8 bits of resolution.
+        let sample = hi_res_sample >> 2;
+        Sample16(sample.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16)
+    }
 }
 
-impl core::convert::Into<u8> for Sample {
-    fn into(self) -> u8 {
-        let mut intermediate: i16 = self.0.into();
-        intermediate += 128;
-        (intermediate as u8)
+/// The PolyBLEP residual for a jump from -1 to 1 at the start of the cycle.
+/// `t` is where we are in the cycle and `dt` is how far we move each sample,
+/// both with 16 fractional bits. Only the sample either side of the jump gets
+/// corrected.
+fn poly_blep(t: i64, dt: i64) -> i64 {
+    const ONE: i64 = 1 << 16;
+    if t < dt {
+        let x = (t << 16) / dt;
+        2 * x - ((x * x) >> 16) - ONE
+    } else if t > ONE - dt {
+        let x = ((t - ONE) << 16) / dt;
+        ((x * x) >> 16) + 2 * x + ONE
+    } else {
+        0
     }
 }
 
-impl Note {
-    pub fn hertz(self) -> f32 {
-        (self as u32) as f32 / 100.0
+/// Read a 256 entry wavetable at a 32-bit phase, blending between the entry
+/// below and the entry above using the next 8 bits of the phase.
+fn interpolate(table: &[i8; 256], phase: u32) -> i8 {
+    let below = i32::from(table[(phase >> 24) as usize]);
+    let above = i32::from(table[((phase >> 24) as u8).wrapping_add(1) as usize]);
+    let fraction = ((phase >> 16) & 0xFF) as i32;
+    (below + (((above - below) * fraction) >> 8)) as i8
+}
+
+/// Work out the frequency ratio for an interval in cents, as a value with 16
+/// fractional bits. So 1200 cents gives `2 << 16` and -1200 cents gives
+/// `1 << 15`.
+fn cents_to_ratio(cents: i32) -> u32 {
+    let octaves = cents.div_euclid(1200);
+    let cents = cents.rem_euclid(1200) as usize;
+    let semitone_ratio = 0x1_0000 + u64::from(SEMITONE_RATIOS[cents / 100]);
+    let cent_ratio = 0x1_0000 + u64::from(CENT_RATIOS[cents % 100]);
+    let ratio = (semitone_ratio * cent_ratio) >> 16;
+    if octaves >= 0 {
+        (ratio << octaves.min(15)).min(u64::from(u32::MAX)) as u32
+    } else {
+        (ratio >> (-octaves).min(31)) as u32
     }
+}
 
-    pub fn centi_hertz(self) -> u32 {
-        self as u32
+/// Squash a sample with the soft clipping curve, interpolating between the
+/// points in our table.
+fn soft_clip(sample: i32) -> i16 {
+    let magnitude = sample.unsigned_abs();
+    let index = (magnitude >> 9) as usize;
+    let clipped = if index >= SOFT_CLIP_257.len() - 1 {
+        i32::from(SOFT_CLIP_257[SOFT_CLIP_257.len() - 1])
+    } else {
+        let lower = i32::from(SOFT_CLIP_257[index]);
+        let upper = i32::from(SOFT_CLIP_257[index + 1]);
+        let fraction = (magnitude & 0x1FF) as i32;
+        lower + (((upper - lower) * fraction) >> 9)
+    };
+    if sample < 0 {
+        -clipped as i16
+    } else {
+        clipped as i16
     }
 }
 
-impl Frequency {
-    pub fn from_hertz(hertz: u16) -> Frequency {
-        Frequency(hertz as u32 * 16)
+impl core::convert::From<Sample16> for Sample {
+    /// Throw away the bottom 8 bits of a 16-bit sample.
+    fn from(sample: Sample16) -> Sample {
+        Sample((sample.0 >> 8) as i8)
     }
+}
 
-    pub fn from_centi_hertz(centi_hertz: u32) -> Frequency {
-        Frequency(centi_hertz)
+impl core::convert::From<Sample16> for i16 {
+    fn from(sample: Sample16) -> i16 {
+        sample.0
     }
+}
 
-    pub fn centi_hertz(&self) -> u32 {
-        self.0
+impl core::convert::From<Sample> for i8 {
+    fn from(sample: Sample) -> i8 {
+        sample.0
     }
 }
 
-impl core::convert::Into<Frequency> for Note {
-    fn into(self) -> Frequency {
-        Frequency::from_centi_hertz(match self {
-            Note::Rest => 0,
-            Note::C0 => 1635,
-            Note::CsDb0 => 1732,
-            Note::D0 => 1835,
-            Note::DsEb0 => 1945,
-            Note::E0 => 2060,
-            Note::F0 => 2183,
-            Note::FsGb0 => 2312,
-            Note::G0 => 2450,
-            Note::GsAb0 => 2596,
-            Note::A0 => 2750,
-            Note::AsBb0 => 2914,
-            Note::B0 => 3087,
-            Note::C1 => 3270,
-            Note::CsDb1 => 3465,
-            Note::D1 => 3671,
-            Note::DsEb1 => 3889,
-            Note::E1 => 4120,
-            Note::F1 => 4365,
-            Note::FsGb1 => 4625,
-            Note::G1 => 4900,
-            Note::GsAb1 => 5191,
-            Note::A1 => 5500,
-            Note::AsBb1 => 5827,
-            Note::B1 => 6174,
-            Note::C2 => 6541,
-            Note::CsDb2 => 6930,
-            Note::D2 => 7342,
-            Note::DsEb2 => 7778,
+impl core::convert::From<Sample> for u8 {
+    fn from(sample: Sample) -> u8 {
+        let mut intermediate: i16 = sample.0.into();
+        intermediate += 128;
+        intermediate as u8
+    }
+}
+
+impl Oscillator {
+    /// A silent oscillator, with no envelope shaping.
+    const OFF: Oscillator = Oscillator {
+        phase_accumulator: 0,
+        phase_step: 0,
+        current_step: 0,
+        portamento: 0,
+        glide_level: 0,
+        glide_step: 0,
+        glide_target: 0,
+        glide_remaining: 0,
+        volume: 0,
+        waveform: Waveform::Sine,
+        interpolation: Interpolation::Nearest,
+        band_limited: false,
+        duty_cycle: 128,
+        lfsr: 1,
+        envelope: EnvelopeGenerator::NONE,
+        lfo: LfoGenerator::NONE,
+        fm: Fm::NONE,
+        fm_phase_accumulator: 0,
+        fm_offset: 0,
+        ring_source: None,
+        sync_source: None,
+        filtered: false,
+        arpeggio: Arpeggio::NONE,
+        arpeggio_idx: 0,
+        arpeggio_remaining: 0,
+        pan_left: 256,
+        pan_right: 256,
+    };
+
+    /// Are we currently making any noise?
+    fn is_active(&self) -> bool {
+        self.volume != 0 && self.envelope.stage != EnvelopeStage::Idle
+    }
+
+    /// Produce our next sample, scaled by our volume and our envelope.
+    #[inline]
+    fn next(&mut self) -> i32 {
+        let (gain, _wrapped) = self.step();
+        self.scale(i32::from(self.sample()), gain)
+    }
+
+    /// Move our envelope, LFO, arpeggio and phase on by one sample. Returns
+    /// the gain to apply to the next sample, where 256 is unity, and whether
+    /// we reached the end of a cycle.
+    fn step(&mut self) -> (i32, bool) {
+        let mut gain = self.envelope.next() as i32;
+        let mut cents = self.next_arpeggio_offset() * 100;
+        if let Some(wobble) = self.lfo.next() {
+            cents += (self.lfo.vibrato_depth * wobble) / 127;
+            // Dip from unity gain when the wobble is at the bottom, to
+            // `256 - tremolo_depth` at the top. The wobble goes from 0 to
+            // 254 here, so stretch it to 0 to 256 first.
+            let dip = ((wobble + 127) * 129 + 64) >> 7;
+            gain = (gain * (256 - ((self.lfo.tremolo_depth * dip) >> 8))) >> 8;
+        }
+        let wrapped = if cents == 0 {
+            self.advance(0x1_0000)
+        } else {
+            self.advance(cents_to_ratio(cents))
+        };
+        (gain, wrapped)
+    }
+
+    /// Scale a raw waveform value by our volume and the given gain.
+    fn scale(&self, value: i32, gain: i32) -> i32 {
+        (value * i32::from(self.volume) * gain) >> 8
+    }
+
+    /// Move one sample along the arpeggio, and return how many semitones
+    /// away from the note we should currently be.
+    fn next_arpeggio_offset(&mut self) -> i32 {
+        let len = usize::from(self.arpeggio.len).min(MAX_ARPEGGIO_LEN);
+        if len == 0 {
+            return 0;
+        }
+        if self.arpeggio_remaining == 0 {
+            self.arpeggio_idx = (self.arpeggio_idx + 1) % len;
+            self.arpeggio_remaining = self.arpeggio.step_samples;
+        }
+        self.arpeggio_remaining = self.arpeggio_remaining.saturating_sub(1);
+        i32::from(self.arpeggio.offsets[self.arpeggio_idx % len])
+    }
+
+    /// Step the phase accumulator on by one sample, with our phase step
+    /// scaled by `ratio` (which has 16 fractional bits), and say whether we
+    /// wrapped around to the start of the waveform. The noise shift register
+    /// is clocked every time we would have moved on to the next entry in a
+    /// 256 entry waveform table, like the noise channels on the SN76489 and
+    /// the NES APU.
+    fn advance(&mut self, ratio: u32) -> bool {
+        if self.glide_remaining != 0 {
+            self.glide_remaining -= 1;
+            if self.glide_remaining == 0 {
+                // Land exactly on the target, whatever rounding we did
+                self.phase_step = self.glide_target;
+            } else {
+                self.glide_level = self.glide_level.wrapping_add(self.glide_step as u64);
+                self.phase_step = (self.glide_level >> 16) as u32;
+            }
+        }
+        let phase_step =
+            ((u64::from(self.phase_step) * u64::from(ratio)) >> 16).min(u64::from(u32::MAX)) as u32;
+        self.current_step = phase_step;
+        if self.fm.index != 0 {
+            let denominator = u64::from(self.fm.denominator.max(1));
+            let fm_step = u64::from(phase_step) * u64::from(self.fm.numerator) / denominator;
+            self.fm_phase_accumulator = self.fm_phase_accumulator.wrapping_add(fm_step as u32);
+            let modulator = i32::from(SINE_256[(self.fm_phase_accumulator >> 24) as usize]);
+            self.fm_offset = ((modulator * i32::from(self.fm.index)) << 19) as u32;
+        }
+        let fraction = u64::from(self.phase_accumulator & 0x00FF_FFFF);
+        let (phase_accumulator, wrapped) = self.phase_accumulator.overflowing_add(phase_step);
+        self.phase_accumulator = phase_accumulator;
+        let tap = match self.waveform {
+            Waveform::Noise => 1,
+            Waveform::PeriodicNoise => 6,
+            _ => return wrapped,
+        };
+        let clocks = (fraction + u64::from(phase_step)) >> 24;
+        for _ in 0..clocks {
+            let feedback = (self.lfsr ^ (self.lfsr >> tap)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        }
+        wrapped
+    }
+
+    /// Look up the waveform at our current phase.
+    fn sample(&self) -> i8 {
+        let phase = self.phase_accumulator.wrapping_add(self.fm_offset);
+        let offset = phase >> 24;
+        match self.waveform {
+            Waveform::Sawtooth | Waveform::Square | Waveform::Pulse if self.band_limited => {
+                self.band_limited_sample(phase, self.current_step)
+            }
+            Waveform::Noise | Waveform::PeriodicNoise => {
+                if self.lfsr & 1 == 0 {
+                    127
+                } else {
+                    -127
+                }
+            }
+            Waveform::Pulse => {
+                if offset < u32::from(self.duty_cycle) {
+                    127
+                } else {
+                    -127
+                }
+            }
+            _ => match self.interpolation {
+                // phase_accumulator is a u32. After >> 24 we get a u8, so this is safe.
+                Interpolation::Nearest => unsafe {
+                    *self.waveform.table().get_unchecked(offset as usize)
+                },
+                Interpolation::Linear => interpolate(self.waveform.table(), phase),
+            },
+        }
+    }
+
+    /// Calculate a sawtooth, square or pulse wave directly from a phase and
+    /// the phase step that got us there, smoothing off each jump with a
+    /// PolyBLEP (polynomial band-limited step).
+    fn band_limited_sample(&self, phase: u32, phase_step: u32) -> i8 {
+        // Everything here has 16 fractional bits, so ONE is a whole cycle.
+        const ONE: i64 = 1 << 16;
+        let t = i64::from(phase >> 16);
+        let dt = i64::from(phase_step >> 16).max(1);
+        let value = match self.waveform {
+            Waveform::Sawtooth => (2 * t - ONE) - poly_blep(t, dt),
+            _ => {
+                let duty = match self.waveform {
+                    Waveform::Square => ONE / 2,
+                    _ => i64::from(self.duty_cycle) << 8,
+                };
+                let naive = if t < duty { ONE } else { -ONE };
+                naive + poly_blep(t, dt) - poly_blep((t + ONE - duty) % ONE, dt)
+            }
+        };
+        ((value * 127) >> 16).clamp(-127, 127) as i8
+    }
+
+    /// Is the only thing changing from one sample to the next our position
+    /// in a wavetable? If so, `render` can take a short cut.
+    fn is_steady(&self) -> bool {
+        let from_table = match self.waveform {
+            Waveform::Noise | Waveform::PeriodicNoise | Waveform::Pulse => false,
+            Waveform::Sawtooth | Waveform::Square => !self.band_limited,
+            _ => true,
+        };
+        let constant_gain = matches!(
+            self.envelope.stage,
+            EnvelopeStage::Sustain | EnvelopeStage::Idle
+        );
+        let unmodulated =
+            self.lfo.vibrato_depth == 0 && self.lfo.tremolo_depth == 0 && self.fm.index == 0;
+        from_table
+            && constant_gain
+            && unmodulated
+            && self.arpeggio.len == 0
+            && self.glide_remaining == 0
+    }
+
+    /// Add our next `accu.len()` samples to `accu`. When we're steady, we can
+    /// skip most of the work `next` does for each sample.
+    fn render(&mut self, accu: &mut [i32]) {
+        if !self.is_steady() {
+            for sample in accu.iter_mut() {
+                *sample += self.next();
+            }
+            return;
+        }
+        let table = self.waveform.table();
+        let scale = i32::from(self.volume) * (self.envelope.level >> 16) as i32;
+        match self.interpolation {
+            Interpolation::Nearest => {
+                for sample in accu.iter_mut() {
+                    self.phase_accumulator = self.phase_accumulator.wrapping_add(self.phase_step);
+                    let offset = self.phase_accumulator >> 24;
+                    // phase_accumulator is a u32. After >> 24 we get a u8, so this is safe.
+                    let value = unsafe { *table.get_unchecked(offset as usize) };
+                    *sample += (i32::from(value) * scale) >> 8;
+                }
+            }
+            Interpolation::Linear => {
+                for sample in accu.iter_mut() {
+                    self.phase_accumulator = self.phase_accumulator.wrapping_add(self.phase_step);
+                    let value = interpolate(table, self.phase_accumulator);
+                    *sample += (i32::from(value) * scale) >> 8;
+                }
+            }
+        }
+    }
+}
+
+impl Waveform {
+    /// Get the wavetable for this waveform. Waveforms which are calculated
+    /// rather than looked up get a sine wave, but you shouldn't ask.
+    fn table(self) -> &'static [i8; 256] {
+        match self {
+            Waveform::Sawtooth => &SAWTOOTH_256,
+            Waveform::Square => &SQUARE_256,
+            Waveform::Triangle => &TRIANGLE_256,
+            Waveform::NesTriangle => &NES_TRIANGLE_256,
+            Waveform::Custom(table) => table,
+            Waveform::Sine | Waveform::Noise | Waveform::PeriodicNoise | Waveform::Pulse => {
+                &SINE_256
+            }
+        }
+    }
+}
+
+impl Envelope {
+    /// No shaping at all - notes start at full volume and stop dead.
+    pub const NONE: Envelope = Envelope {
+        attack: 0,
+        decay: 0,
+        sustain: 255,
+        release: 0,
+    };
+}
+
+impl StateVariableFilter {
+    /// A low-pass filter at the highest cutoff, with no resonance.
+    const OFF: StateVariableFilter = StateVariableFilter {
+        mode: FilterMode::LowPass,
+        frequency: 1 << 16,
+        damping: 92_682,
+        low: 0,
+        band: 0,
+    };
+
+    /// Filter one sample.
+    fn process(&mut self, input: i32) -> i32 {
+        self.low += (self.frequency * self.band) >> 16;
+        let high = i64::from(input) - self.low - ((self.damping * self.band) >> 16);
+        self.band += (self.frequency * high) >> 16;
+        let output = match self.mode {
+            FilterMode::LowPass => self.low,
+            FilterMode::BandPass => self.band,
+            FilterMode::HighPass => high,
+        };
+        // A resonant filter can ring far louder than its input. Anything
+        // past 2^22 clips in the output anyway, and keeping below it means
+        // adding the direct channels and applying the master volume can't
+        // overflow an i32.
+        const LIMIT: i64 = 1 << 22;
+        output.clamp(-LIMIT, LIMIT) as i32
+    }
+}
+
+impl Arpeggio {
+    /// No arpeggio - just play the note.
+    pub const NONE: Arpeggio = Arpeggio {
+        offsets: [0; MAX_ARPEGGIO_LEN],
+        len: 0,
+        step_samples: 0,
+    };
+
+    /// Make an arpeggio from a list of semitone offsets, each of which lasts
+    /// `step_samples` samples. Only the first `MAX_ARPEGGIO_LEN` offsets are
+    /// used.
+    pub const fn new(offsets: &[i8], step_samples: u32) -> Arpeggio {
+        let mut arpeggio = Arpeggio {
+            offsets: [0; MAX_ARPEGGIO_LEN],
+            len: 0,
+            step_samples,
+        };
+        while (arpeggio.len as usize) < offsets.len() && (arpeggio.len as usize) < MAX_ARPEGGIO_LEN
+        {
+            arpeggio.offsets[arpeggio.len as usize] = offsets[arpeggio.len as usize];
+            arpeggio.len += 1;
+        }
+        arpeggio
+    }
+}
+
+impl Fm {
+    /// No FM.
+    pub const NONE: Fm = Fm {
+        numerator: 1,
+        denominator: 1,
+        index: 0,
+    };
+}
+
+impl Lfo {
+    /// No wobble at all.
+    pub const NONE: Lfo = Lfo {
+        rate: Frequency(0),
+        vibrato_depth: 0,
+        tremolo_depth: 0,
+        delay: 0,
+    };
+}
+
+impl LfoGenerator {
+    /// A generator configured as per `Lfo::NONE`.
+    const NONE: LfoGenerator = LfoGenerator {
+        phase_accumulator: 0,
+        phase_step: 0,
+        vibrato_depth: 0,
+        tremolo_depth: 0,
+        delay_samples: 0,
+        delay_remaining: 0,
+    };
+
+    /// Move one sample along the LFO, and return where the wobble is, from
+    /// -127 to 127, or `None` if we're still waiting for the delay to pass
+    /// (or there's nothing to wobble).
+    fn next(&mut self) -> Option<i32> {
+        if self.vibrato_depth == 0 && self.tremolo_depth == 0 {
+            return None;
+        }
+        if self.delay_remaining != 0 {
+            self.delay_remaining -= 1;
+            return None;
+        }
+        self.phase_accumulator = self.phase_accumulator.wrapping_add(self.phase_step);
+        Some(i32::from(SINE_256[(self.phase_accumulator >> 24) as usize]))
+    }
+}
+
+impl EnvelopeGenerator {
+    /// An idle generator configured as per `Envelope::NONE`.
+    const NONE: EnvelopeGenerator = EnvelopeGenerator {
+        stage: EnvelopeStage::Idle,
+        level: 0,
+        attack_step: ENVELOPE_FULL,
+        decay_step: ENVELOPE_FULL,
+        sustain_level: ENVELOPE_FULL,
+        release_step: ENVELOPE_FULL,
+        release_ms: 0,
+    };
+
+    /// Move one sample along the envelope, and return the gain to apply to
+    /// that sample, where 256 is unity.
+    fn next(&mut self) -> u32 {
+        match self.stage {
+            EnvelopeStage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= ENVELOPE_FULL {
+                    self.level = ENVELOPE_FULL;
+                    self.stage = EnvelopeStage::Decay;
+                }
+            }
+            EnvelopeStage::Decay => {
+                if self.level > self.sustain_level + self.decay_step {
+                    self.level -= self.decay_step;
+                } else {
+                    self.level = self.sustain_level;
+                    self.stage = EnvelopeStage::Sustain;
+                }
+            }
+            EnvelopeStage::Release => {
+                self.level = self.level.saturating_sub(self.release_step);
+                if self.level == 0 {
+                    self.stage = EnvelopeStage::Idle;
+                }
+            }
+            EnvelopeStage::Sustain | EnvelopeStage::Idle => {}
+        }
+        self.level >> 16
+    }
+}
+
+/// An iterator over the notes from one note to another, inclusive. See
+/// `Note::range`.
+#[derive(Debug, Clone)]
+pub struct NoteRange {
+    /// The discriminant of the next note from the front.
+    front: u8,
+    /// The discriminant of the next note from the back.
+    back: u8,
+}
+
+/// The ways parsing a `Note` from a string can fail.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParseNoteError {
+    /// The string was empty.
+    Empty,
+    /// The note name wasn't one of A to G.
+    BadName,
+    /// The octave was missing, or wasn't a single digit.
+    BadOctave,
+    /// The note is valid, but below C0 or above B8 (say, `Cb0`).
+    OutOfRange,
+}
+
+impl Note {
+    /// The MIDI note number of C0, our lowest note.
+    const MIDI_C0: u8 = 12;
+
+    /// The MIDI note number of B8, our highest note.
+    const MIDI_B8: u8 = 119;
+
+    /// Get the note with this discriminant.
+    fn from_u8(value: u8) -> Note {
+        assert!(value <= Note::B8 as u8);
+        // Note is `repr(u8)` and its discriminants run from 0 (Rest) to B8
+        // without any gaps, which we just checked.
+        unsafe { core::mem::transmute::<u8, Note>(value) }
+    }
+
+    /// Get the note for a MIDI note number, where 60 is C4 (middle C) and 69
+    /// is A4. Returns `None` for notes outside C0 (12) to B8 (119).
+    pub fn from_midi(midi: u8) -> Option<Note> {
+        if (Note::MIDI_C0..=Note::MIDI_B8).contains(&midi) {
+            Some(Note::from_u8(midi - Note::MIDI_C0 + Note::C0 as u8))
+        } else {
+            None
+        }
+    }
+
+    /// Get the MIDI note number for this note. `Note::Rest` doesn't have one.
+    pub fn to_midi(self) -> Option<u8> {
+        match self {
+            Note::Rest => None,
+            _ => Some(self as u8 - Note::C0 as u8 + Note::MIDI_C0),
+        }
+    }
+
+    /// Move the note up (or, if negative, down) by some number of semitones.
+    /// Returns `None` if that takes us below C0 or above B8. A `Note::Rest`
+    /// stays a rest.
+    pub fn transpose(self, semitones: i8) -> Option<Note> {
+        match self.to_midi() {
+            None => Some(Note::Rest),
+            Some(midi) => {
+                let midi = i16::from(midi) + i16::from(semitones);
+                if midi < 0 || midi > i16::from(u8::MAX) {
+                    None
+                } else {
+                    Note::from_midi(midi as u8)
+                }
+            }
+        }
+    }
+
+    /// Which octave the note is in, where C4 (middle C) is in octave 4.
+    /// `Note::Rest` isn't in any octave.
+    pub fn octave(self) -> Option<u8> {
+        self.to_midi().map(|midi| midi / 12 - 1)
+    }
+
+    /// Which of the twelve notes of the octave this is. `Note::Rest` isn't
+    /// any of them.
+    pub fn pitch_class(self) -> Option<PitchClass> {
+        self.to_midi()
+            .map(|midi| PitchClass::ALL[usize::from(midi % 12)])
+    }
+
+    /// Iterate over every note from `start` to `end`, inclusive, in order.
+    /// If `end` is lower than `start`, or either is a `Note::Rest`, there are
+    /// no notes at all.
+    pub fn range(start: Note, end: Note) -> NoteRange {
+        if start == Note::Rest || end == Note::Rest {
+            NoteRange { front: 1, back: 0 }
+        } else {
+            NoteRange {
+                front: start as u8,
+                back: end as u8,
+            }
+        }
+    }
+
+    /// The frequency of this note, in hertz, as an `f32`.
+    #[deprecated(
+        note = "use `hertz_f32` (with the `float` feature), or `Frequency::from(note).hertz()` for whole hertz"
+    )]
+    pub fn hertz(self) -> f32 {
+        self.centi_hertz() as f32 / 100.0
+    }
+
+    /// The frequency of this note, in hertz, as an `f32`. This is the same as
+    /// `Frequency::from(note).hertz_f32()`.
+    #[cfg(feature = "float")]
+    pub fn hertz_f32(self) -> f32 {
+        Frequency::from(self).hertz_f32()
+    }
+
+    /// The frequency of this note, in centi-hertz. This is the same as
+    /// `Frequency::from(note).centi_hertz()`.
+    pub fn centi_hertz(self) -> u32 {
+        Frequency::from(self).centi_hertz()
+    }
+}
+
+impl Frequency {
+    /// A frequency in whole hertz.
+    pub fn from_hertz(hertz: u16) -> Frequency {
+        Frequency(u32::from(hertz) * 100)
+    }
+
+    /// A frequency in hundredths of a hertz.
+    pub fn from_centi_hertz(centi_hertz: u32) -> Frequency {
+        Frequency(centi_hertz)
+    }
+
+    /// A frequency in thousandths of a hertz, rounded to the nearest
+    /// centi-hertz.
+    pub fn from_millihertz(millihertz: u32) -> Frequency {
+        Frequency(millihertz / 10 + u32::from(millihertz % 10 >= 5))
+    }
+
+    /// A frequency in hertz, rounded to the nearest centi-hertz. Negative
+    /// frequencies become zero.
+    #[cfg(feature = "float")]
+    pub fn from_hertz_f32(hertz: f32) -> Frequency {
+        // `as` saturates, so anything too big or too small is clamped.
+        Frequency((hertz * 100.0 + 0.5) as u32)
+    }
+
+    /// The frequency in whole hertz, rounded down.
+    pub fn hertz(&self) -> u32 {
+        self.0 / 100
+    }
+
+    /// The frequency in hertz.
+    #[cfg(feature = "float")]
+    pub fn hertz_f32(&self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+
+    /// The frequency in hundredths of a hertz.
+    pub fn centi_hertz(&self) -> u32 {
+        self.0
+    }
+
+    /// Multiply the frequency by `numerator / denominator`. So `scale(3, 2)`
+    /// is a perfect fifth above, and `scale(1, 2)` is an octave below. A zero
+    /// `denominator` is treated as one.
+    pub fn scale(self, numerator: u32, denominator: u32) -> Frequency {
+        let scaled = u64::from(self.0) * u64::from(numerator) / u64::from(denominator.max(1));
+        Frequency(scaled.min(u64::from(u32::MAX)) as u32)
+    }
+
+    /// Move the frequency up (or, if negative, down) by some number of
+    /// cents, where 100 cents is a semitone and 1200 cents is an octave.
+    pub fn offset_cents(self, cents: i32) -> Frequency {
+        let offset = (u64::from(self.0) * u64::from(cents_to_ratio(cents)) + 0x8000) >> 16;
+        Frequency(offset.min(u64::from(u32::MAX)) as u32)
+    }
+}
+
+impl Temperament {
+    /// The offset, in cents, from equal temperament of each note of the
+    /// octave, starting from the key note.
+    fn offsets(&self) -> [i16; 12] {
+        match self {
+            Temperament::Equal => [0; 12],
+            // 1/1, 16/15, 9/8, 6/5, 5/4, 4/3, 45/32, 3/2, 8/5, 5/3, 16/9, 15/8
+            Temperament::Just => [0, 12, 4, 16, -14, -2, -10, 2, 14, -16, -4, -12],
+            // 1/1, 256/243, 9/8, 32/27, 81/64, 4/3, 729/512, 3/2, 128/81,
+            // 27/16, 16/9, 243/128
+            Temperament::Pythagorean => [0, -10, 4, -6, 8, -2, 12, 2, -8, 6, -4, 10],
+            Temperament::Custom(offsets) => *offsets,
+        }
+    }
+}
+
+impl PitchClass {
+    /// Every pitch class, in order, starting from C.
+    pub const ALL: [PitchClass; 12] = [
+        PitchClass::C,
+        PitchClass::CsDb,
+        PitchClass::D,
+        PitchClass::DsEb,
+        PitchClass::E,
+        PitchClass::F,
+        PitchClass::FsGb,
+        PitchClass::G,
+        PitchClass::GsAb,
+        PitchClass::A,
+        PitchClass::AsBb,
+        PitchClass::B,
+    ];
+}
+
+impl core::str::FromStr for Note {
+    type Err = ParseNoteError;
+
+    /// Parse a note name, like `C4`, `C#4`, `Db4` or `r` for a rest. The
+    /// letter and the rest can be either case, and enharmonics like `E#4`
+    /// and `Cb4` are allowed.
+    fn from_str(s: &str) -> Result<Note, ParseNoteError> {
+        if s.eq_ignore_ascii_case("r") || s.eq_ignore_ascii_case("rest") {
+            return Ok(Note::Rest);
+        }
+        let mut bytes = s.bytes();
+        let semitone: i16 = match bytes.next().map(|b| b.to_ascii_uppercase()) {
+            None => return Err(ParseNoteError::Empty),
+            Some(b'C') => 0,
+            Some(b'D') => 2,
+            Some(b'E') => 4,
+            Some(b'F') => 5,
+            Some(b'G') => 7,
+            Some(b'A') => 9,
+            Some(b'B') => 11,
+            Some(_) => return Err(ParseNoteError::BadName),
+        };
+        let mut next = bytes.next();
+        let semitone = match next {
+            Some(b'#') => {
+                next = bytes.next();
+                semitone + 1
+            }
+            Some(b'b') => {
+                next = bytes.next();
+                semitone - 1
+            }
+            _ => semitone,
+        };
+        let octave = match (next, bytes.next()) {
+            (Some(digit @ b'0'..=b'9'), None) => i16::from(digit - b'0'),
+            _ => return Err(ParseNoteError::BadOctave),
+        };
+        let midi = (octave + 1) * 12 + semitone;
+        if midi < 0 {
+            return Err(ParseNoteError::OutOfRange);
+        }
+        Note::from_midi(midi as u8).ok_or(ParseNoteError::OutOfRange)
+    }
+}
+
+impl core::fmt::Display for Note {
+    /// Writes the note name in a form `from_str` can read back, like `C4`,
+    /// `C#4` or `r`. Black notes are always written as sharps.
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        const NAMES: [&str; 12] = [
+            "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+        ];
+        match (self.pitch_class(), self.octave()) {
+            (Some(pitch_class), Some(octave)) => {
+                write!(f, "{}{}", NAMES[pitch_class as usize], octave)
+            }
+            _ => f.write_str("r"),
+        }
+    }
+}
+
+impl core::fmt::Display for ParseNoteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(match self {
+            ParseNoteError::Empty => "empty note name",
+            ParseNoteError::BadName => "note name should be A to G",
+            ParseNoteError::BadOctave => "note octave should be a single digit",
+            ParseNoteError::OutOfRange => "note is outside C0 to B8",
+        })
+    }
+}
+
+impl Iterator for NoteRange {
+    type Item = Note;
+
+    fn next(&mut self) -> Option<Note> {
+        if self.front > self.back {
+            return None;
+        }
+        let note = Note::from_u8(self.front);
+        self.front += 1;
+        Some(note)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = usize::from(self.back + 1).saturating_sub(usize::from(self.front));
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for NoteRange {
+    fn next_back(&mut self) -> Option<Note> {
+        if self.front > self.back {
+            return None;
+        }
+        let note = Note::from_u8(self.back);
+        self.back -= 1;
+        Some(note)
+    }
+}
+
+impl ExactSizeIterator for NoteRange {}
+
+impl Tuning {
+    /// A4 = 440 Hz, in equal temperament.
+    pub const STANDARD: Tuning = Tuning {
+        reference: Frequency(44000),
+        temperament: Temperament::Equal,
+        key: PitchClass::C,
+    };
+}
+
+impl Pitch for Note {
+    fn frequency(self, tuning: &Tuning) -> Frequency {
+        let pitch_class = match self.pitch_class() {
+            Some(pitch_class) => pitch_class,
+            None => return Frequency(0),
+        };
+        let equal = Frequency::from(self).scale(tuning.reference.centi_hertz(), 44000);
+        let offsets = tuning.temperament.offsets();
+        let degree =
+            |pitch_class: PitchClass| usize::from((pitch_class as u8 + 12 - tuning.key as u8) % 12);
+        // Shift everything so that A4 lands on the reference pitch.
+        let offset =
+            i32::from(offsets[degree(pitch_class)]) - i32::from(offsets[degree(PitchClass::A)]);
+        equal.offset_cents(offset)
+    }
+}
+
+impl Pitch for Frequency {
+    /// A `Frequency` is played as is, whatever the tuning.
+    fn frequency(self, _tuning: &Tuning) -> Frequency {
+        self
+    }
+}
+
+impl core::convert::From<Note> for Frequency {
+    fn from(note: Note) -> Frequency {
+        Frequency::from_centi_hertz(match note {
+            Note::Rest => 0,
+            Note::C0 => 1635,
+            Note::CsDb0 => 1732,
+            Note::D0 => 1835,
+            Note::DsEb0 => 1945,
+            Note::E0 => 2060,
+            Note::F0 => 2183,
+            Note::FsGb0 => 2312,
+            Note::G0 => 2450,
+            Note::GsAb0 => 2596,
+            Note::A0 => 2750,
+            Note::AsBb0 => 2914,
+            Note::B0 => 3087,
+            Note::C1 => 3270,
+            Note::CsDb1 => 3465,
+            Note::D1 => 3671,
+            Note::DsEb1 => 3889,
+            Note::E1 => 4120,
+            Note::F1 => 4365,
+            Note::FsGb1 => 4625,
+            Note::G1 => 4900,
+            Note::GsAb1 => 5191,
+            Note::A1 => 5500,
+            Note::AsBb1 => 5827,
+            Note::B1 => 6174,
+            Note::C2 => 6541,
+            Note::CsDb2 => 6930,
+            Note::D2 => 7342,
+            Note::DsEb2 => 7778,
             Note::E2 => 8241,
             Note::F2 => 8731,
             Note::FsGb2 => 9250,
@@ -498,3 +2311,1177 @@ impl core::convert::Into<Frequency> for Note {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    /// Check a triangle table is odd-symmetric, rises to its peak at index
+    /// 64, falls to its trough at index 192 and rises again.
+    fn check_triangle(table: &[i8; 256]) {
+        for i in 0..128 {
+            assert_eq!(table[i], -table[i + 128], "index {}", i);
+        }
+        assert_eq!(table[64], 127);
+        assert_eq!(table[192], -127);
+        for i in 0..255 {
+            if (64..192).contains(&i) {
+                assert!(table[i + 1] <= table[i], "index {}", i);
+            } else {
+                assert!(table[i + 1] >= table[i], "index {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_shape() {
+        check_triangle(&TRIANGLE_256);
+    }
+
+    #[test]
+    fn nes_triangle_shape() {
+        check_triangle(&NES_TRIANGLE_256);
+        let mut levels = [false; 256];
+        for &value in NES_TRIANGLE_256.iter() {
+            levels[(i16::from(value) + 128) as usize] = true;
+        }
+        assert_eq!(levels.iter().filter(|&&seen| seen).count(), 16);
+    }
+
+    static FULL_POSITIVE: [i8; 256] = [127; 256];
+    static FULL_NEGATIVE: [i8; 256] = [-127; 256];
+
+    /// The first 16-bit sample from a synth with every channel playing the
+    /// same flat waveform at full volume.
+    fn extreme<const N: usize>(clip_mode: ClipMode, table: &'static [i8; 256]) -> i16 {
+        let mut synth = Synth::<N>::new(8000);
+        synth.set_clip_mode(clip_mode);
+        for idx in 0..N {
+            let channel = Channel::new(idx).unwrap();
+            synth.play(channel, Note::A4, MAX_VOLUME, Waveform::Custom(table));
+        }
+        synth.next_i16().into()
+    }
+
+    #[test]
+    fn downmix_clamps() {
+        assert_eq!(i8::from(Synth::<3>::downmix(1 << 20)), 127);
+        assert_eq!(i8::from(Synth::<3>::downmix(-(1 << 20))), -128);
+        assert_eq!(i16::from(Synth::<3>::downmix_i16(1 << 20)), i16::MAX);
+        assert_eq!(i16::from(Synth::<3>::downmix_i16(-(1 << 20))), i16::MIN);
+    }
+
+    #[test]
+    fn hard_clip_extremes() {
+        // Three channels fit without clipping...
+        assert_eq!(
+            extreme::<3>(ClipMode::Hard, &FULL_POSITIVE),
+            ((3 * 127 * 255) >> 2) as i16
+        );
+        assert_eq!(
+            extreme::<3>(ClipMode::Hard, &FULL_NEGATIVE),
+            ((-3 * 127 * 255) >> 2) as i16
+        );
+        // ...but eight don't.
+        assert_eq!(extreme::<8>(ClipMode::Hard, &FULL_POSITIVE), i16::MAX);
+        assert_eq!(extreme::<8>(ClipMode::Hard, &FULL_NEGATIVE), i16::MIN);
+    }
+
+    #[test]
+    fn soft_clip_extremes() {
+        let loud = extreme::<8>(ClipMode::Soft, &FULL_POSITIVE);
+        assert!(loud > 30000 && loud < i16::MAX, "{}", loud);
+        assert_eq!(extreme::<8>(ClipMode::Soft, &FULL_NEGATIVE), -loud);
+        let quiet = extreme::<3>(ClipMode::Soft, &FULL_POSITIVE);
+        assert!(quiet > 0 && quiet < loud, "{}", quiet);
+        assert_eq!(extreme::<3>(ClipMode::Soft, &FULL_NEGATIVE), -quiet);
+    }
+
+    #[test]
+    fn headroom_extremes() {
+        for &(table, expected) in &[(&FULL_POSITIVE, 127 * 255), (&FULL_NEGATIVE, -127 * 255)] {
+            assert_eq!(extreme::<3>(ClipMode::Headroom, table), expected);
+            assert_eq!(extreme::<8>(ClipMode::Headroom, table), expected);
+        }
+    }
+
+    #[test]
+    fn full_tremolo_reaches_silence() {
+        // At this sample rate, a 1 Hz LFO lands on every entry of its sine
+        // table, including the peak.
+        let mut synth = Synth::<3>::new(256);
+        let lfo = Lfo {
+            rate: Frequency::from_hertz(1),
+            vibrato_depth: 0,
+            tremolo_depth: 255,
+            delay: 0,
+        };
+        synth.set_lfo(CHANNEL_0, lfo);
+        synth.play(
+            CHANNEL_0,
+            Note::A4,
+            MAX_VOLUME,
+            Waveform::Custom(&FULL_POSITIVE),
+        );
+        let mut quietest = i16::MAX;
+        let mut loudest = 0;
+        for _ in 0..256 {
+            let sample = i16::from(synth.next_i16());
+            quietest = quietest.min(sample);
+            loudest = loudest.max(sample);
+        }
+        assert_eq!(quietest, 0);
+        assert_eq!(loudest, (127 * 255) >> 2);
+    }
+
+    /// The peak level of a sine wave at `hertz`, once it has settled,
+    /// through a filter with a 1 kHz cutoff and no resonance.
+    fn filtered_peak(mode: Option<FilterMode>, hertz: u16) -> i32 {
+        let mut synth = Synth::<3>::new(37_878);
+        if let Some(mode) = mode {
+            synth.set_filter(Filter {
+                mode,
+                cutoff: Frequency::from_hertz(1000),
+                resonance: 0,
+            });
+            synth.set_filter_routing(CHANNEL_0, true);
+        }
+        let note = Frequency::from_hertz(hertz);
+        synth.play(CHANNEL_0, note, MAX_VOLUME, Waveform::Sine);
+        let mut peak = 0;
+        for idx in 0..8000 {
+            let sample = i32::from(i16::from(synth.next_i16()));
+            if idx >= 4000 {
+                peak = peak.max(sample.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn filter_response() {
+        let low = filtered_peak(None, 100);
+        let mid = filtered_peak(None, 1000);
+        let high = filtered_peak(None, 5000);
+
+        // Low-pass: 100 Hz passes, 5 kHz is cut to well under a tenth.
+        assert!(filtered_peak(Some(FilterMode::LowPass), 100) * 10 > low * 9);
+        assert!(filtered_peak(Some(FilterMode::LowPass), 5000) * 10 < high);
+
+        // High-pass: the other way around.
+        assert!(filtered_peak(Some(FilterMode::HighPass), 100) * 10 < low);
+        assert!(filtered_peak(Some(FilterMode::HighPass), 5000) * 10 > high * 9);
+
+        // Band-pass: both are cut to under a third of the level at the cutoff.
+        let centre = filtered_peak(Some(FilterMode::BandPass), 1000);
+        assert!(centre * 2 > mid);
+        assert!(filtered_peak(Some(FilterMode::BandPass), 100) * 3 < centre);
+        assert!(filtered_peak(Some(FilterMode::BandPass), 5000) * 3 < centre);
+    }
+
+    #[test]
+    fn filter_stable_at_full_resonance() {
+        for &mode in &[
+            FilterMode::LowPass,
+            FilterMode::BandPass,
+            FilterMode::HighPass,
+        ] {
+            let mut synth = Synth::<3>::new(37_878);
+            // Far above the limit, so this is clamped to a sixth of the
+            // sample rate.
+            synth.set_filter(Filter {
+                mode,
+                cutoff: Frequency::from_hertz(20_000),
+                resonance: 255,
+            });
+            synth.set_filter_routing(CHANNEL_0, true);
+            synth.set_filter_routing(CHANNEL_1, true);
+            synth.play(CHANNEL_0, Note::C2, MAX_VOLUME, Waveform::Square);
+            synth.play(CHANNEL_1, Note::C4, MAX_VOLUME, Waveform::Noise);
+            for _ in 0..37_878 * 4 {
+                synth.next_i16();
+            }
+            let svf = &synth.filter_left;
+            assert!(svf.low.abs() < 1 << 24, "{:?}: low {}", mode, svf.low);
+            assert!(svf.band.abs() < 1 << 24, "{:?}: band {}", mode, svf.band);
+            // Once the notes stop, it should ring down to nothing.
+            synth.off(CHANNEL_0);
+            synth.off(CHANNEL_1);
+            for _ in 0..37_878 {
+                synth.next_i16();
+            }
+            assert_eq!(i16::from(synth.next_i16()), 0, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn note_pitch_error() {
+        // Three cents is a frequency ratio of about 1.00173.
+        const MAX_ERROR: f64 = 0.00173;
+        for &sample_rate in &[8000, 22_050, 37_878, 44_100, 48_000] {
+            let synth = Synth::<3>::new(sample_rate);
+            for note in Note::range(Note::C0, Note::B8) {
+                let wanted = f64::from(note.centi_hertz());
+                let step = synth.frequency_to_phase_step(note.into());
+                let played = f64::from(step) * f64::from(sample_rate) * 100.0 / 4_294_967_296.0;
+                let error = (played / wanted - 1.0).abs();
+                assert!(error < MAX_ERROR, "{:?} at {} Hz", note, sample_rate);
+            }
+        }
+    }
+
+    /// How much of the power of a 2.5 kHz note played at 32 kHz lies away
+    /// from its harmonics. At that rate the output repeats exactly every 64
+    /// samples, so a 64 point DFT has every harmonic (and every alias) on a
+    /// bin of its own.
+    fn alias_ratio(band_limited: bool, waveform: Waveform, arpeggio: bool) -> f64 {
+        let mut synth = Synth::<3>::new(32_000);
+        synth.set_band_limited(band_limited);
+        let note = if arpeggio {
+            synth.set_arpeggio(CHANNEL_0, Arpeggio::new(&[12], u32::MAX));
+            Frequency::from_hertz(1250)
+        } else {
+            Frequency::from_hertz(2500)
+        };
+        synth.play(CHANNEL_0, note, MAX_VOLUME, waveform);
+        for _ in 0..1000 {
+            synth.next_i16();
+        }
+        let mut samples = [0f64; 64];
+        for sample in samples.iter_mut() {
+            *sample = f64::from(i16::from(synth.next_i16()));
+        }
+        let mut harmonics = 0.0;
+        let mut aliases = 0.0;
+        for bin in 1..=32 {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (idx, sample) in samples.iter().enumerate() {
+                let angle = 2.0 * core::f64::consts::PI * (bin * idx) as f64 / 64.0;
+                re += sample * angle.cos();
+                im += sample * angle.sin();
+            }
+            // Our harmonics are every 2.5 kHz, which is every fifth bin.
+            if bin % 5 == 0 {
+                harmonics += re * re + im * im;
+            } else {
+                aliases += re * re + im * im;
+            }
+        }
+        aliases / harmonics
+    }
+
+    #[test]
+    fn band_limited_aliasing() {
+        // -20 dB and -15 dB, as power ratios.
+        const QUIET: f64 = 0.01;
+        const LOUD: f64 = 0.0316;
+        for &waveform in &[Waveform::Sawtooth, Waveform::Square, Waveform::Pulse] {
+            assert!(alias_ratio(false, waveform, false) > LOUD, "{:?}", waveform);
+            assert!(alias_ratio(true, waveform, false) < QUIET, "{:?}", waveform);
+            assert!(alias_ratio(true, waveform, true) < QUIET, "{:?}", waveform);
+        }
+    }
+
+    #[test]
+    fn frequency_from_hertz() {
+        // This used to multiply by 16 rather than 100.
+        assert_eq!(Frequency::from_hertz(440).centi_hertz(), 44000);
+        assert_eq!(
+            Frequency::from_hertz(440),
+            Frequency::from_centi_hertz(44000)
+        );
+        assert_eq!(Frequency::from_hertz(u16::MAX).hertz(), 65535);
+        assert_eq!(Frequency::from_centi_hertz(44099).hertz(), 440);
+    }
+
+    #[test]
+    fn frequency_from_millihertz() {
+        assert_eq!(Frequency::from_millihertz(440_000).centi_hertz(), 44000);
+        assert_eq!(Frequency::from_millihertz(440_004).centi_hertz(), 44000);
+        assert_eq!(Frequency::from_millihertz(440_005).centi_hertz(), 44001);
+        assert_eq!(Frequency::from_millihertz(4).centi_hertz(), 0);
+        assert_eq!(
+            Frequency::from_millihertz(u32::MAX).centi_hertz(),
+            429_496_730
+        );
+    }
+
+    #[cfg(feature = "float")]
+    #[test]
+    fn frequency_from_hertz_f32() {
+        assert_eq!(Frequency::from_hertz_f32(440.0).centi_hertz(), 44000);
+        assert_eq!(Frequency::from_hertz_f32(261.626).centi_hertz(), 26163);
+        assert_eq!(Frequency::from_hertz_f32(-5.0).centi_hertz(), 0);
+        assert_eq!(Frequency::from_centi_hertz(44000).hertz_f32(), 440.0);
+    }
+
+    #[test]
+    fn frequency_scale() {
+        let a4 = Frequency::from_hertz(440);
+        assert_eq!(a4.scale(3, 2).centi_hertz(), 66000);
+        assert_eq!(a4.scale(1, 2).centi_hertz(), 22000);
+        assert_eq!(a4.scale(7, 0), a4.scale(7, 1));
+        assert_eq!(a4.scale(u32::MAX, 1).centi_hertz(), u32::MAX);
+    }
+
+    #[test]
+    fn frequency_offset_cents() {
+        let a4 = Frequency::from_hertz(440);
+        assert_eq!(a4.offset_cents(0), a4);
+        assert_eq!(a4.offset_cents(1200).centi_hertz(), 88000);
+        assert_eq!(a4.offset_cents(-1200).centi_hertz(), 22000);
+        // A semitone either side, to the nearest centi-hertz of A#4 and G#4.
+        let up = a4.offset_cents(100).centi_hertz();
+        let down = a4.offset_cents(-100).centi_hertz();
+        assert!((46615..=46617).contains(&up), "{}", up);
+        assert!((41529..=41531).contains(&down), "{}", down);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn note_frequency_getters() {
+        for note in Note::range(Note::C0, Note::B8) {
+            let frequency = Frequency::from(note);
+            assert_eq!(note.centi_hertz(), frequency.centi_hertz());
+            assert_eq!(note.hertz(), frequency.centi_hertz() as f32 / 100.0);
+            #[cfg(feature = "float")]
+            assert_eq!(note.hertz_f32(), frequency.hertz_f32());
+        }
+        assert_eq!(Note::A4.centi_hertz(), 44000);
+        assert_eq!(Note::Rest.centi_hertz(), 0);
+    }
+
+    /// The frequency ratio between two notes in a tuning.
+    fn interval(tuning: &Tuning, low: Note, high: Note) -> f64 {
+        let low = low.frequency(tuning).centi_hertz();
+        let high = high.frequency(tuning).centi_hertz();
+        f64::from(high) / f64::from(low)
+    }
+
+    /// Is this ratio within two cents of `numerator / denominator`?
+    fn near_ratio(ratio: f64, numerator: u32, denominator: u32) -> bool {
+        let wanted = f64::from(numerator) / f64::from(denominator);
+        (ratio / wanted - 1.0).abs() < 0.00116
+    }
+
+    #[test]
+    fn tuning_reference_pitch() {
+        for &hertz in &[432, 440, 442] {
+            let tuning = Tuning {
+                reference: Frequency::from_hertz(hertz),
+                ..Tuning::STANDARD
+            };
+            assert_eq!(Note::A4.frequency(&tuning), Frequency::from_hertz(hertz));
+            assert_eq!(
+                Note::A5.frequency(&tuning),
+                Frequency::from_hertz(hertz * 2)
+            );
+            assert!(near_ratio(
+                interval(&tuning, Note::C4, Note::A4),
+                44000,
+                26163
+            ));
+        }
+        assert_eq!(
+            Note::C4.frequency(&Tuning::STANDARD),
+            Frequency::from(Note::C4)
+        );
+        let anything = Frequency::from_centi_hertz(12345);
+        let tuning = Tuning {
+            reference: Frequency::from_hertz(432),
+            ..Tuning::STANDARD
+        };
+        assert_eq!(anything.frequency(&tuning), anything);
+        assert_eq!(Note::Rest.frequency(&tuning).centi_hertz(), 0);
+    }
+
+    #[test]
+    fn tuning_just_intonation() {
+        for &key in &[PitchClass::C, PitchClass::D] {
+            let tuning = Tuning {
+                temperament: Temperament::Just,
+                key,
+                ..Tuning::STANDARD
+            };
+            // A4 stays put, whatever the key.
+            assert_eq!(Note::A4.frequency(&tuning), Frequency::from_hertz(440));
+        }
+        let tuning = Tuning {
+            temperament: Temperament::Just,
+            key: PitchClass::C,
+            ..Tuning::STANDARD
+        };
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::E4), 5, 4));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::F4), 4, 3));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::G4), 3, 2));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::A4), 5, 3));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::B4), 15, 8));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::C5), 2, 1));
+    }
+
+    #[test]
+    fn tuning_pythagorean() {
+        let tuning = Tuning {
+            temperament: Temperament::Pythagorean,
+            key: PitchClass::D,
+            ..Tuning::STANDARD
+        };
+        assert_eq!(Note::A4.frequency(&tuning), Frequency::from_hertz(440));
+        assert!(near_ratio(interval(&tuning, Note::D4, Note::A4), 3, 2));
+        assert!(near_ratio(interval(&tuning, Note::D4, Note::E4), 9, 8));
+        assert!(near_ratio(interval(&tuning, Note::D4, Note::FsGb4), 81, 64));
+        assert!(near_ratio(interval(&tuning, Note::D4, Note::G4), 4, 3));
+        assert!(near_ratio(interval(&tuning, Note::D4, Note::D5), 2, 1));
+    }
+
+    #[test]
+    fn tuning_custom() {
+        let mut offsets = [0; 12];
+        offsets[4] = -14;
+        let tuning = Tuning {
+            temperament: Temperament::Custom(offsets),
+            key: PitchClass::C,
+            ..Tuning::STANDARD
+        };
+        assert_eq!(Note::A4.frequency(&tuning), Frequency::from_hertz(440));
+        assert_eq!(Note::C4.frequency(&tuning), Frequency::from(Note::C4));
+        assert!(near_ratio(interval(&tuning, Note::C4, Note::E4), 5, 4));
+    }
+
+    #[test]
+    fn note_midi_round_trip() {
+        let mut count = 0;
+        for (idx, note) in Note::range(Note::C0, Note::B8).enumerate() {
+            let midi = note.to_midi().unwrap();
+            assert_eq!(usize::from(midi), idx + 12);
+            assert_eq!(Note::from_midi(midi), Some(note));
+            assert_eq!(usize::from(note.octave().unwrap()), idx / 12);
+            assert_eq!(note.pitch_class(), Some(PitchClass::ALL[idx % 12]));
+            count += 1;
+        }
+        assert_eq!(count, 108);
+        assert_eq!(Note::from_midi(60), Some(Note::C4));
+        assert_eq!(Note::from_midi(69), Some(Note::A4));
+        assert_eq!(Note::from_midi(11), None);
+        assert_eq!(Note::from_midi(120), None);
+        assert_eq!(Note::Rest.to_midi(), None);
+        assert_eq!(Note::Rest.octave(), None);
+        assert_eq!(Note::Rest.pitch_class(), None);
+    }
+
+    #[test]
+    fn note_transpose() {
+        assert_eq!(Note::C4.transpose(7), Some(Note::G4));
+        assert_eq!(Note::C4.transpose(-12), Some(Note::C3));
+        assert_eq!(Note::C4.transpose(0), Some(Note::C4));
+        assert_eq!(Note::AsBb8.transpose(1), Some(Note::B8));
+        assert_eq!(Note::B8.transpose(1), None);
+        assert_eq!(Note::CsDb0.transpose(-1), Some(Note::C0));
+        assert_eq!(Note::C0.transpose(-1), None);
+        assert_eq!(Note::B8.transpose(i8::MAX), None);
+        assert_eq!(Note::C0.transpose(i8::MIN), None);
+        assert_eq!(Note::Rest.transpose(5), Some(Note::Rest));
+    }
+
+    #[test]
+    fn note_range() {
+        let mut range = Note::range(Note::A4, Note::C5);
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.next(), Some(Note::A4));
+        assert_eq!(range.next_back(), Some(Note::C5));
+        assert_eq!(range.len(), 2);
+        assert_eq!(range.next_back(), Some(Note::B4));
+        assert_eq!(range.next(), Some(Note::AsBb4));
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+
+        let mut reversed = Note::range(Note::A4, Note::C5).rev();
+        assert_eq!(reversed.next(), Some(Note::C5));
+        assert_eq!(reversed.next(), Some(Note::B4));
+        assert_eq!(reversed.next(), Some(Note::AsBb4));
+        assert_eq!(reversed.next(), Some(Note::A4));
+        assert_eq!(reversed.next(), None);
+
+        assert_eq!(Note::range(Note::C5, Note::C5).len(), 1);
+        assert_eq!(Note::range(Note::C0, Note::B8).len(), 108);
+        assert_eq!(Note::range(Note::C5, Note::A4).len(), 0);
+        assert_eq!(Note::range(Note::C5, Note::A4).next(), None);
+        assert_eq!(Note::range(Note::Rest, Note::A4).len(), 0);
+        assert_eq!(Note::range(Note::A4, Note::Rest).next_back(), None);
+    }
+
+    #[test]
+    fn parse_note_names() {
+        let cases = [
+            ("C4", Ok(Note::C4)),
+            ("c4", Ok(Note::C4)),
+            ("C#4", Ok(Note::CsDb4)),
+            ("Db4", Ok(Note::CsDb4)),
+            ("Bb3", Ok(Note::AsBb3)),
+            ("bb3", Ok(Note::AsBb3)),
+            ("A0", Ok(Note::A0)),
+            ("B8", Ok(Note::B8)),
+            ("E#4", Ok(Note::F4)),
+            ("Fb4", Ok(Note::E4)),
+            ("Cb4", Ok(Note::B3)),
+            ("B#3", Ok(Note::C4)),
+            ("r", Ok(Note::Rest)),
+            ("R", Ok(Note::Rest)),
+            ("rest", Ok(Note::Rest)),
+            ("REST", Ok(Note::Rest)),
+            ("", Err(ParseNoteError::Empty)),
+            ("H4", Err(ParseNoteError::BadName)),
+            ("#4", Err(ParseNoteError::BadName)),
+            ("\u{e9}4", Err(ParseNoteError::BadName)),
+            ("C", Err(ParseNoteError::BadOctave)),
+            ("C#", Err(ParseNoteError::BadOctave)),
+            ("C10", Err(ParseNoteError::BadOctave)),
+            ("C-1", Err(ParseNoteError::BadOctave)),
+            ("C##4", Err(ParseNoteError::BadOctave)),
+            ("C4 ", Err(ParseNoteError::BadOctave)),
+            ("Cb0", Err(ParseNoteError::OutOfRange)),
+            ("B#8", Err(ParseNoteError::OutOfRange)),
+            ("C9", Err(ParseNoteError::OutOfRange)),
+        ];
+        for &(text, expected) in cases.iter() {
+            assert_eq!(text.parse::<Note>(), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn display_note_names() {
+        assert_eq!(std::format!("{}", Note::C4), "C4");
+        assert_eq!(std::format!("{}", Note::CsDb4), "C#4");
+        assert_eq!(std::format!("{}", Note::AsBb3), "A#3");
+        assert_eq!(std::format!("{}", Note::Rest), "r");
+        for note in Note::range(Note::C0, Note::B8) {
+            let text = std::format!("{}", note);
+            assert_eq!(text.parse::<Note>(), Ok(note), "{}", text);
+        }
+        assert_eq!("r".parse::<Note>(), Ok(Note::Rest));
+    }
+
+    /// Step the envelope on channel 0 until it leaves `stage`, and say how
+    /// many samples that took.
+    fn samples_in_stage(synth: &mut Synth<3>, stage: EnvelopeStage) -> usize {
+        let mut samples = 0;
+        while synth.channels[0].envelope.stage == stage {
+            synth.channels[0].envelope.next();
+            samples += 1;
+            assert!(samples < 100_000, "stuck in {:?}", stage);
+        }
+        samples
+    }
+
+    #[test]
+    fn envelope_attack_length() {
+        // At 1 kHz, each millisecond is one sample.
+        let mut synth = Synth::<3>::new(1000);
+        let envelope = Envelope {
+            attack: 16,
+            decay: 0,
+            sustain: 255,
+            release: 0,
+        };
+        synth.set_envelope(CHANNEL_0, envelope);
+        synth.note_on(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Sine);
+        assert_eq!(samples_in_stage(&mut synth, EnvelopeStage::Attack), 16);
+        assert_eq!(synth.channels[0].envelope.level, ENVELOPE_FULL);
+        // A length which doesn't divide the range exactly lands within a
+        // sample of it.
+        synth.set_envelope(
+            CHANNEL_0,
+            Envelope {
+                attack: 10,
+                ..envelope
+            },
+        );
+        synth.off(CHANNEL_0);
+        synth.channels[0].envelope.level = 0;
+        synth.note_on(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Sine);
+        let samples = samples_in_stage(&mut synth, EnvelopeStage::Attack);
+        assert!((10..=11).contains(&samples), "{}", samples);
+    }
+
+    #[test]
+    fn envelope_lands_on_sustain() {
+        let mut synth = Synth::<3>::new(1000);
+        let envelope = Envelope {
+            attack: 0,
+            decay: 7,
+            sustain: 100,
+            release: 0,
+        };
+        synth.set_envelope(CHANNEL_0, envelope);
+        synth.note_on(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Sine);
+        samples_in_stage(&mut synth, EnvelopeStage::Attack);
+        let samples = samples_in_stage(&mut synth, EnvelopeStage::Decay);
+        assert!((7..=8).contains(&samples), "{}", samples);
+        let env = &mut synth.channels[0].envelope;
+        assert_eq!(env.stage, EnvelopeStage::Sustain);
+        assert_eq!(env.level, (u64::from(ENVELOPE_FULL) * 100 / 255) as u32);
+        for _ in 0..100 {
+            assert_eq!(env.next(), 100 * 256 / 255);
+        }
+    }
+
+    #[test]
+    fn envelope_releases_from_current_level() {
+        let mut synth = Synth::<3>::new(1000);
+        let envelope = Envelope {
+            attack: 16,
+            decay: 0,
+            sustain: 255,
+            release: 16,
+        };
+        synth.set_envelope(CHANNEL_0, envelope);
+        synth.note_on(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Sine);
+        // Halfway up the attack...
+        for _ in 0..8 {
+            synth.channels[0].envelope.next();
+        }
+        let level = synth.channels[0].envelope.level;
+        assert_eq!(level, ENVELOPE_FULL / 2);
+        // ...then release, which should take the full release time to get
+        // down from there, rather than jumping up to full first.
+        synth.note_off(CHANNEL_0);
+        assert_eq!(synth.channels[0].envelope.release_step, level / 16);
+        assert!(synth.channels[0].envelope.next() < level >> 16);
+        let samples = 1 + samples_in_stage(&mut synth, EnvelopeStage::Release);
+        assert_eq!(samples, 16);
+        assert_eq!(synth.channels[0].envelope.next(), 0);
+    }
+
+    #[test]
+    fn envelope_none_is_instant() {
+        let mut synth = Synth::<3>::new(1000);
+        synth.set_envelope(CHANNEL_0, Envelope::NONE);
+        synth.note_on(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Sine);
+        let env = &mut synth.channels[0].envelope;
+        assert_eq!(env.next(), 256);
+        assert_eq!(env.next(), 256);
+        assert_eq!(env.next(), 256);
+        assert_eq!(env.stage, EnvelopeStage::Sustain);
+        synth.note_off(CHANNEL_0);
+        let env = &mut synth.channels[0].envelope;
+        assert_eq!(env.next(), 0);
+        assert_eq!(env.stage, EnvelopeStage::Idle);
+    }
+
+    /// How many clocks it takes the noise shift register to get back to its
+    /// seed of 1.
+    fn lfsr_period(waveform: Waveform) -> u32 {
+        let mut osc = Oscillator::OFF;
+        osc.waveform = waveform;
+        // One table entry per sample, so one clock per sample.
+        osc.phase_step = 1 << 24;
+        assert_eq!(osc.lfsr, 1);
+        let mut period = 0;
+        loop {
+            osc.advance(0x1_0000);
+            period += 1;
+            if osc.lfsr == 1 || period > 100_000 {
+                return period;
+            }
+        }
+    }
+
+    #[test]
+    fn noise_periods() {
+        assert_eq!(lfsr_period(Waveform::Noise), 32767);
+        assert_eq!(lfsr_period(Waveform::PeriodicNoise), 93);
+    }
+
+    /// Changes the settings of a synth before we render from it.
+    type Setup = fn(&mut Synth<3>);
+
+    /// Set up a synth with three notes playing, then let `setup` change it.
+    fn render_synth(setup: Setup) -> Synth<3> {
+        let mut synth = Synth::<3>::new(8000);
+        synth.play(CHANNEL_0, Note::A3, 200, Waveform::Sine);
+        synth.play(CHANNEL_1, Note::E4, 150, Waveform::Sawtooth);
+        synth.play(CHANNEL_2, Note::CsDb5, 100, Waveform::Triangle);
+        synth.set_pan(CHANNEL_0, 0);
+        synth.set_pan(CHANNEL_2, 200);
+        setup(&mut synth);
+        synth
+    }
+
+    /// Check every `render_*` function gives the same samples as calling the
+    /// matching `next*` function once per sample. The odd chunk size means
+    /// blocks start part of the way through a note's changes.
+    fn check_render_matches_next(name: &str, setup: Setup) {
+        const LEN: usize = 1000;
+        const CHUNK: usize = 77;
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0i16; LEN];
+        for sample in expected.iter_mut() {
+            *sample = by_sample.next_i16().into();
+        }
+        let mut rendered = [0i16; LEN];
+        for chunk in rendered.chunks_mut(CHUNK) {
+            by_block.render_i16(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_i16", name);
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0u8; LEN];
+        for sample in expected.iter_mut() {
+            *sample = by_sample.next().into();
+        }
+        let mut rendered = [0u8; LEN];
+        for chunk in rendered.chunks_mut(CHUNK) {
+            by_block.render_u8(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_u8", name);
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0i8; LEN];
+        for sample in expected.iter_mut() {
+            *sample = by_sample.next().into();
+        }
+        let mut rendered = [0i8; LEN];
+        for chunk in rendered.chunks_mut(CHUNK) {
+            by_block.render_i8(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_i8", name);
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0i16; LEN * 2];
+        for frame in expected.chunks_exact_mut(2) {
+            let (left, right) = by_sample.next_stereo_i16();
+            frame[0] = left.into();
+            frame[1] = right.into();
+        }
+        let mut rendered = [0i16; LEN * 2];
+        for chunk in rendered.chunks_mut(CHUNK * 2) {
+            by_block.render_stereo_i16(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_stereo_i16", name);
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0u8; LEN * 2];
+        for frame in expected.chunks_exact_mut(2) {
+            let (left, right) = by_sample.next_stereo();
+            frame[0] = left.into();
+            frame[1] = right.into();
+        }
+        let mut rendered = [0u8; LEN * 2];
+        for chunk in rendered.chunks_mut(CHUNK * 2) {
+            by_block.render_stereo_u8(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_stereo_u8", name);
+
+        let mut by_sample = render_synth(setup);
+        let mut by_block = render_synth(setup);
+        let mut expected = [0i8; LEN * 2];
+        for frame in expected.chunks_exact_mut(2) {
+            let (left, right) = by_sample.next_stereo();
+            frame[0] = left.into();
+            frame[1] = right.into();
+        }
+        let mut rendered = [0i8; LEN * 2];
+        for chunk in rendered.chunks_mut(CHUNK * 2) {
+            by_block.render_stereo_i8(chunk);
+        }
+        assert_eq!(expected[..], rendered[..], "{}: render_stereo_i8", name);
+    }
+
+    #[test]
+    fn render_matches_next() {
+        let setups: [(&str, Setup); 16] = [
+            ("steady", |_| {}),
+            ("envelope", |synth| {
+                let envelope = Envelope {
+                    attack: 20,
+                    decay: 30,
+                    sustain: 100,
+                    release: 40,
+                };
+                synth.set_envelope(CHANNEL_0, envelope);
+                synth.set_envelope(CHANNEL_1, envelope);
+                synth.note_on(CHANNEL_0, Note::A3, 200, Waveform::Sine);
+                synth.note_on(CHANNEL_1, Note::E4, 150, Waveform::Sawtooth);
+                synth.note_off(CHANNEL_1);
+            }),
+            ("lfo", |synth| {
+                synth.set_lfo(
+                    CHANNEL_1,
+                    Lfo {
+                        rate: Frequency::from_hertz(6),
+                        vibrato_depth: 50,
+                        tremolo_depth: 100,
+                        delay: 30,
+                    },
+                );
+            }),
+            ("arpeggio", |synth| {
+                synth.set_arpeggio(CHANNEL_0, Arpeggio::new(&[0, 4, 7], 50));
+            }),
+            ("fm", |synth| {
+                synth.set_fm(
+                    CHANNEL_2,
+                    Fm {
+                        numerator: 3,
+                        denominator: 2,
+                        index: 40,
+                    },
+                );
+            }),
+            ("glide", |synth| {
+                synth.set_portamento(CHANNEL_0, 300);
+                synth.slide_to(CHANNEL_0, Note::A4);
+            }),
+            ("noise and pulse", |synth| {
+                synth.set_waveform(CHANNEL_0, Waveform::Noise);
+                synth.set_waveform(CHANNEL_1, Waveform::PeriodicNoise);
+                synth.set_waveform(CHANNEL_2, Waveform::Pulse);
+                synth.set_duty_cycle(CHANNEL_2, 40);
+            }),
+            ("interpolation", |synth| {
+                synth.set_interpolation(Interpolation::Linear);
+            }),
+            ("band limited", |synth| {
+                synth.set_band_limited(true);
+                synth.set_waveform(CHANNEL_2, Waveform::Square);
+            }),
+            ("filter", |synth| {
+                synth.set_filter(Filter {
+                    mode: FilterMode::LowPass,
+                    cutoff: Frequency::from_hertz(500),
+                    resonance: 200,
+                });
+                synth.set_filter_routing(CHANNEL_1, true);
+            }),
+            ("fade", |synth| {
+                synth.fade_to(0, 700);
+            }),
+            ("soft clip", |synth| {
+                synth.set_clip_mode(ClipMode::Soft);
+            }),
+            ("headroom", |synth| {
+                synth.set_clip_mode(ClipMode::Headroom);
+                let envelope = Envelope {
+                    release: 50,
+                    ..Envelope::NONE
+                };
+                synth.set_envelope(CHANNEL_2, envelope);
+                synth.note_on(CHANNEL_2, Note::CsDb5, 100, Waveform::Triangle);
+                synth.next_i16();
+                synth.note_off(CHANNEL_2);
+            }),
+            ("ring mod", |synth| {
+                synth.set_ring_mod(CHANNEL_1, Some(CHANNEL_0));
+            }),
+            ("sync", |synth| {
+                synth.set_sync(CHANNEL_2, Some(CHANNEL_0));
+            }),
+            ("everything", |synth| {
+                synth.set_interpolation(Interpolation::Linear);
+                synth.set_band_limited(true);
+                synth.set_clip_mode(ClipMode::Soft);
+                synth.set_arpeggio(CHANNEL_1, Arpeggio::new(&[0, 12], 20));
+                synth.set_lfo(
+                    CHANNEL_0,
+                    Lfo {
+                        rate: Frequency::from_hertz(5),
+                        vibrato_depth: 30,
+                        tremolo_depth: 30,
+                        delay: 0,
+                    },
+                );
+                synth.set_filter(Filter {
+                    mode: FilterMode::BandPass,
+                    cutoff: Frequency::from_hertz(800),
+                    resonance: 100,
+                });
+                synth.set_filter_routing(CHANNEL_2, true);
+                synth.fade_to(100, 500);
+            }),
+        ];
+        for &(name, setup) in setups.iter() {
+            check_render_matches_next(name, setup);
+        }
+    }
+
+    #[test]
+    fn master_volume_round_trip() {
+        let mut synth = Synth::<3>::new(8000);
+        assert_eq!(synth.master_volume(), MAX_VOLUME);
+        for volume in 0..=255u8 {
+            synth.set_master_volume(volume);
+            assert_eq!(synth.master_volume(), volume);
+            assert!(!synth.is_fading());
+        }
+        // MAX_VOLUME is exactly unity gain, and zero is silence.
+        synth.set_master_volume(MAX_VOLUME);
+        assert_eq!(synth.next_master_gain(), 256);
+        synth.set_master_volume(0);
+        assert_eq!(synth.next_master_gain(), 0);
+    }
+
+    #[test]
+    fn fade_lands_on_target() {
+        for &(from, to, samples) in &[(255, 0, 1000), (0, 255, 333), (200, 201, 7), (10, 10, 5)] {
+            let mut synth = Synth::<3>::new(8000);
+            synth.set_master_volume(from);
+            synth.fade_to(to, samples);
+            for _ in 0..samples {
+                assert!(synth.is_fading());
+                synth.next_i16();
+            }
+            assert!(!synth.is_fading());
+            assert_eq!(synth.master_volume(), to);
+            assert_eq!(synth.master_level, Synth::<3>::master_level(to));
+            synth.next_i16();
+            assert_eq!(synth.master_volume(), to);
+        }
+        // A zero length fade jumps straight there.
+        let mut synth = Synth::<3>::new(8000);
+        synth.fade_to(64, 0);
+        assert!(!synth.is_fading());
+        assert_eq!(synth.master_volume(), 64);
+        // And setting the volume cancels a fade.
+        synth.fade_to(0, 100);
+        synth.next_i16();
+        synth.set_master_volume(99);
+        assert!(!synth.is_fading());
+        synth.next_i16();
+        assert_eq!(synth.master_volume(), 99);
+    }
+
+    #[test]
+    fn slide_reaches_target() {
+        for &(from, to) in &[(Note::C4, Note::G4), (Note::G4, Note::C3)] {
+            let mut synth = Synth::<3>::new(8000);
+            synth.set_portamento(CHANNEL_0, 100);
+            synth.play(CHANNEL_0, from, MAX_VOLUME, Waveform::Sine);
+            for _ in 0..10 {
+                synth.next_i16();
+            }
+            let start = synth.channels[0].phase_step;
+            let target = synth.frequency_to_phase_step(to.into());
+            let phase = synth.channels[0].phase_accumulator;
+            synth.slide_to(CHANNEL_0, to);
+            // The waveform carries on from where it was.
+            assert_eq!(synth.channels[0].phase_accumulator, phase);
+            let mut previous = start;
+            for _ in 0..99 {
+                synth.next_i16();
+                let step = synth.channels[0].phase_step;
+                // Always heading towards the target, without getting there
+                // early.
+                if target > start {
+                    assert!(step >= previous && step < target);
+                } else {
+                    assert!(step <= previous && step > target);
+                }
+                previous = step;
+            }
+            synth.next_i16();
+            assert_eq!(synth.channels[0].phase_step, target);
+            assert_eq!(synth.channels[0].glide_remaining, 0);
+            synth.next_i16();
+            assert_eq!(synth.channels[0].phase_step, target);
+        }
+    }
+
+    #[test]
+    fn slide_without_portamento_jumps() {
+        let mut synth = Synth::<3>::new(8000);
+        synth.play(CHANNEL_0, Note::C4, MAX_VOLUME, Waveform::Sine);
+        synth.next_i16();
+        let phase = synth.channels[0].phase_accumulator;
+        synth.slide_to(CHANNEL_0, Note::C5);
+        assert_eq!(synth.channels[0].phase_accumulator, phase);
+        assert_eq!(
+            synth.channels[0].phase_step,
+            synth.frequency_to_phase_step(Note::C5.into())
+        );
+        assert_eq!(synth.channels[0].glide_remaining, 0);
+    }
+
+    #[test]
+    fn lfo_rate_saturates() {
+        let mut synth = Synth::<3>::new(8000);
+        let lfo = Lfo {
+            rate: Frequency::from_hertz(5),
+            vibrato_depth: 100,
+            tremolo_depth: 0,
+            delay: 0,
+        };
+        synth.set_lfo(CHANNEL_0, lfo);
+        assert_eq!(
+            synth.channels[0].lfo.phase_step,
+            synth.frequency_to_phase_step(Frequency::from_hertz(5))
+        );
+        for &hertz in &[8000, 8001, 16_000, u16::MAX] {
+            let rate = Frequency::from_hertz(hertz);
+            synth.set_lfo(CHANNEL_0, Lfo { rate, ..lfo });
+            assert_eq!(synth.channels[0].lfo.phase_step, u32::MAX, "{} Hz", hertz);
+        }
+    }
+
+    #[test]
+    fn arpeggio_timing() {
+        let mut synth = Synth::<3>::new(8000);
+        synth.set_arpeggio(CHANNEL_0, Arpeggio::new(&[0, 4, 7], 3));
+        synth.play(CHANNEL_0, Note::C4, MAX_VOLUME, Waveform::Sine);
+        let osc = &mut synth.channels[0];
+        // Each note lasts exactly three samples, then we go back to the top.
+        for &expected in &[0, 0, 0, 4, 4, 4, 7, 7, 7, 0, 0, 0, 4, 4, 4, 7] {
+            assert_eq!(osc.next_arpeggio_offset(), expected);
+        }
+        // A new note starts again from the top.
+        synth.play(CHANNEL_0, Note::C4, MAX_VOLUME, Waveform::Sine);
+        assert_eq!(synth.channels[0].next_arpeggio_offset(), 0);
+    }
+
+    #[test]
+    fn arpeggio_shrinks_mid_cycle() {
+        let mut synth = Synth::<3>::new(8000);
+        synth.set_arpeggio(CHANNEL_0, Arpeggio::new(&[0, 4, 7, 12], 2));
+        synth.play(CHANNEL_0, Note::C4, MAX_VOLUME, Waveform::Sine);
+        for _ in 0..7 {
+            synth.channels[0].next_arpeggio_offset();
+        }
+        // We're on the last note of four. Swap in a two note arpeggio.
+        assert_eq!(synth.channels[0].next_arpeggio_offset(), 12);
+        synth.set_arpeggio(CHANNEL_0, Arpeggio::new(&[0, 5], 2));
+        let osc = &mut synth.channels[0];
+        for _ in 0..20 {
+            let offset = osc.next_arpeggio_offset();
+            assert!(offset == 0 || offset == 5, "{}", offset);
+        }
+        // And turning it off mid-cycle leaves the pitch alone.
+        synth.set_arpeggio(CHANNEL_0, Arpeggio::NONE);
+        assert_eq!(synth.channels[0].next_arpeggio_offset(), 0);
+    }
+
+    #[test]
+    fn hard_sync_resets_phase() {
+        let mut synth = Synth::<3>::new(8000);
+        synth.play(CHANNEL_0, Note::A3, MAX_VOLUME, Waveform::Sawtooth);
+        synth.play(CHANNEL_1, Note::E5, MAX_VOLUME, Waveform::Sawtooth);
+        synth.set_sync(CHANNEL_1, Some(CHANNEL_0));
+        let step = synth.channels[1].phase_step;
+        let mut resets = 0;
+        for _ in 0..1000 {
+            let source = synth.channels[0].phase_accumulator;
+            let ours = synth.channels[1].phase_accumulator;
+            synth.next_channels();
+            if synth.channels[0].phase_accumulator < source {
+                // The source wrapped, so we start again from the top.
+                assert_eq!(synth.channels[1].phase_accumulator, 0);
+                resets += 1;
+            } else {
+                assert_eq!(synth.channels[1].phase_accumulator, ours.wrapping_add(step));
+            }
+        }
+        // A3 is 220 Hz, so that's 27 or 28 cycles of the source.
+        assert!((27..=28).contains(&resets), "{}", resets);
+    }
+
+    #[test]
+    fn ring_mod_multiplies() {
+        let mut synth = Synth::<3>::new(8000);
+        synth.play(CHANNEL_0, Note::A3, MAX_VOLUME, Waveform::Sine);
+        synth.play(CHANNEL_1, Note::E5, MAX_VOLUME, Waveform::Triangle);
+        synth.set_ring_mod(CHANNEL_1, Some(CHANNEL_0));
+        let mut nonzero = 0;
+        for _ in 0..1000 {
+            let samples = synth.next_channels();
+            let a = i32::from(synth.channels[0].sample());
+            let b = i32::from(synth.channels[1].sample());
+            // With no envelope, the gain is unity, so each channel is just
+            // scaled by its volume.
+            assert_eq!(samples[0], a * i32::from(MAX_VOLUME));
+            assert_eq!(samples[1], ((a * b) >> 7) * i32::from(MAX_VOLUME));
+            if samples[1] != 0 {
+                nonzero += 1;
+            }
+        }
+        assert!(nonzero > 500);
+    }
+
+    #[test]
+    fn resonant_filter_many_channels() {
+        // Sixteen channels of full volume noise, all in step, through a
+        // screaming filter mustn't overflow anywhere, whichever way we mix.
+        // The slow noise makes big steps which set the filter ringing.
+        let setup = || {
+            let mut synth = Synth::<16>::new(8000);
+            synth.set_filter(Filter {
+                mode: FilterMode::HighPass,
+                cutoff: Frequency::from_hertz(20_000),
+                resonance: 255,
+            });
+            for idx in 0..16 {
+                let channel = Channel::new(idx).unwrap();
+                synth.set_filter_routing(channel, true);
+                synth.play(channel, Note::C0, MAX_VOLUME, Waveform::Noise);
+            }
+            synth
+        };
+        let mut synth = setup();
+        for _ in 0..8000 {
+            synth.next_i16();
+            synth.next_stereo_i16();
+        }
+        let mut synth = setup();
+        let mut buffer = [0i16; 8000];
+        synth.render_i16(&mut buffer);
+        synth.render_stereo_i16(&mut buffer);
+        assert!(buffer.iter().any(|&sample| sample != 0));
+    }
+
+    #[test]
+    fn headroom_render_matches_next() {
+        let setup = || {
+            let mut synth = Synth::<3>::new(8000);
+            synth.set_clip_mode(ClipMode::Headroom);
+            let envelope = Envelope {
+                attack: 0,
+                decay: 0,
+                sustain: 255,
+                release: 10,
+            };
+            synth.set_envelope(CHANNEL_0, envelope);
+            synth.play(CHANNEL_0, Note::A4, MAX_VOLUME, Waveform::Square);
+            synth.play(CHANNEL_1, Note::E5, MAX_VOLUME, Waveform::Sine);
+            for _ in 0..100 {
+                synth.next_i16();
+            }
+            synth.note_off(CHANNEL_0);
+            synth
+        };
+        let mut by_sample = setup();
+        let mut by_block = setup();
+        let mut expected = [0i16; 200];
+        for sample in expected.iter_mut() {
+            *sample = by_sample.next_i16().into();
+        }
+        let mut rendered = [0i16; 200];
+        by_block.render_i16(&mut rendered);
+        assert_eq!(expected[..], rendered[..]);
+        let mut expected = [0i16; 400];
+        let mut by_sample = setup();
+        let mut by_block = setup();
+        for frame in expected.chunks_exact_mut(2) {
+            let (left, right) = by_sample.next_stereo_i16();
+            frame[0] = left.into();
+            frame[1] = right.into();
+        }
+        let mut rendered = [0i16; 400];
+        by_block.render_stereo_i16(&mut rendered);
+        assert_eq!(expected[..], rendered[..]);
+    }
+}